@@ -0,0 +1,310 @@
+use std::{collections::HashMap, env};
+
+use anyhow::bail;
+use csv::QuoteStyle;
+
+use crate::{json::KeyCase, template::Template};
+
+pub const ENV_PREFIX: &str = "FORMAT_OPTION_";
+
+/// How many rows the streaming writers buffer before flushing, unless `flush_rows` is set.
+pub const DEFAULT_FLUSH_ROWS: usize = 10_000;
+
+/// Per-format settings, collected from `FORMAT_OPTION_<KEY>` environment variables. Each writer
+/// reads the keys it understands.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    options: HashMap<String, String>,
+}
+
+impl FormatOptions {
+    pub fn from_env() -> Self {
+        env::vars()
+            .filter_map(|(name, value)| {
+                name.strip_prefix(ENV_PREFIX)
+                    .map(|key| (key.to_ascii_lowercase(), value))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(String::as_str)
+    }
+
+    pub fn set<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.options.insert(key.into(), value.into());
+    }
+
+    /// Returns the keys that none of the given known keys account for, in sorted order.
+    pub fn unknown_keys(&self, known: &[&str]) -> Vec<&str> {
+        let mut unknown: Vec<&str> = self
+            .options
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !known.contains(key))
+            .collect();
+        unknown.sort_unstable();
+        unknown
+    }
+
+    pub fn delimiter(&self) -> anyhow::Result<Option<u8>> {
+        match self.get("delimiter") {
+            None => Ok(None),
+            Some("\\t") | Some("tab") => Ok(Some(b'\t')),
+            Some(value) if value.len() == 1 => Ok(Some(value.as_bytes()[0])),
+            Some(value) => bail!("delimiter must be a single byte, got {:?}", value),
+        }
+    }
+
+    pub fn quote_style(&self) -> anyhow::Result<Option<QuoteStyle>> {
+        match self.get("quote").map(str::to_ascii_lowercase).as_deref() {
+            None => Ok(None),
+            Some("always") => Ok(Some(QuoteStyle::Always)),
+            Some("necessary") => Ok(Some(QuoteStyle::Necessary)),
+            Some("non-numeric") | Some("non_numeric") => Ok(Some(QuoteStyle::NonNumeric)),
+            Some("never") => Ok(Some(QuoteStyle::Never)),
+            Some(other) => bail!(
+                "unsupported quote style {:?} (expected always, necessary, non-numeric or never)",
+                other
+            ),
+        }
+    }
+
+    pub fn key_case(&self) -> anyhow::Result<KeyCase> {
+        match self
+            .get("key_case")
+            .map(|key_case| key_case.to_ascii_lowercase().replace('_', "-"))
+            .as_deref()
+        {
+            None | Some("as-is") => Ok(KeyCase::AsIs),
+            Some("snake") => Ok(KeyCase::Snake),
+            Some("camel") => Ok(KeyCase::Camel),
+            Some(other) => bail!(
+                "unsupported key case {:?} (expected as-is, snake or camel)",
+                other
+            ),
+        }
+    }
+
+    pub fn flag(&self, key: &str) -> anyhow::Result<bool> {
+        match self.get(key).map(str::to_ascii_lowercase).as_deref() {
+            None => Ok(false),
+            Some("1") | Some("true") | Some("yes") | Some("on") => Ok(true),
+            Some("0") | Some("false") | Some("no") | Some("off") => Ok(false),
+            Some(other) => bail!("{} must be true or false, got {:?}", key, other),
+        }
+    }
+
+    pub fn field_separator(&self) -> anyhow::Result<Option<String>> {
+        self.separator("field_separator")
+    }
+
+    pub fn record_separator(&self) -> anyhow::Result<Option<String>> {
+        self.separator("record_separator")
+    }
+
+    /// Reads a separator, unescaping `\t`, `\r`, `\n`, and `\\` so control characters can be
+    /// given in environment variables.
+    fn separator(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let value = match self.get(key) {
+            None => return Ok(None),
+            Some(value) => value,
+        };
+
+        let mut separator = String::new();
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                separator.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('t') => separator.push('\t'),
+                Some('r') => separator.push('\r'),
+                Some('n') => separator.push('\n'),
+                Some('\\') => separator.push('\\'),
+                _ => bail!("{} has an unsupported escape in {:?}", key, value),
+            }
+        }
+
+        if separator.is_empty() || separator.contains('"') {
+            bail!(
+                "{} must be non-empty and not contain '\"', got {:?}",
+                key,
+                value
+            );
+        }
+        Ok(Some(separator))
+    }
+
+    pub fn line_ending(&self) -> anyhow::Result<&'static str> {
+        match self
+            .get("line_ending")
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            None | Some("lf") => Ok("\n"),
+            Some("crlf") => Ok("\r\n"),
+            Some(other) => bail!("unsupported line ending {:?} (expected lf or crlf)", other),
+        }
+    }
+
+    /// Whether the output ends with a line ending, which is the default for every format.
+    pub fn final_newline(&self) -> anyhow::Result<bool> {
+        match self.get("final_newline") {
+            None => Ok(true),
+            Some(_) => self.flag("final_newline"),
+        }
+    }
+
+    pub fn flush_rows(&self) -> anyhow::Result<usize> {
+        match self.get("flush_rows") {
+            None => Ok(DEFAULT_FLUSH_ROWS),
+            Some(value) => match value.parse::<usize>() {
+                Ok(rows) if rows > 0 => Ok(rows),
+                _ => bail!("flush_rows must be a positive number, got {:?}", value),
+            },
+        }
+    }
+
+    /// The indentation for pretty-printed JSON, or `None` for compact output. Setting `indent`
+    /// (a number of spaces up to 16, or `tab`) implies `pretty`, which indents by two spaces.
+    pub fn indent(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.get("indent").map(str::to_ascii_lowercase).as_deref() {
+            None if self.flag("pretty")? => Ok(Some(b"  ".to_vec())),
+            None => Ok(None),
+            Some("tab") | Some("\\t") => Ok(Some(b"\t".to_vec())),
+            Some(value) => match value.parse::<usize>() {
+                Ok(width) if width <= 16 => Ok(Some(vec![b' '; width])),
+                _ => bail!(
+                    "indent must be a number of spaces up to 16 or tab, got {:?}",
+                    value
+                ),
+            },
+        }
+    }
+
+    /// The key that wraps the rows in JSON output, `data` unless overridden. It must be an
+    /// identifier: ASCII letters, digits, and underscores, not starting with a digit.
+    pub fn root_key(&self) -> anyhow::Result<&str> {
+        match self.get("root_key") {
+            None => Ok("data"),
+            Some(key)
+                if key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    && key.chars().next().is_some_and(|c| !c.is_ascii_digit()) =>
+            {
+                Ok(key)
+            }
+            Some(key) => bail!("root_key must be a non-empty identifier, got {:?}", key),
+        }
+    }
+
+    /// The key JSON output adds to each row for its 1-based row number, if any. A flag value such
+    /// as `true` picks the default key, `_row`.
+    pub fn row_index(&self) -> anyhow::Result<Option<&str>> {
+        match self.get("row_index") {
+            None => Ok(None),
+            Some("") => bail!("row_index must not be empty"),
+            Some(key)
+                if matches!(
+                    key.to_ascii_lowercase().as_str(),
+                    "1" | "true" | "yes" | "on"
+                ) =>
+            {
+                Ok(Some("_row"))
+            }
+            Some(key) => Ok(Some(key)),
+        }
+    }
+
+    /// The columns whose `0`/`1` values JSON output writes as `false`/`true`, from a
+    /// comma-separated list.
+    pub fn bool_columns(&self) -> Vec<&str> {
+        self.get("bool_columns")
+            .map(|columns| {
+                columns
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|column| !column.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Parses every typed option so bad values are reported before any work is done.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.delimiter()?;
+        self.quote_style()?;
+        self.flag("null_aware")?;
+        self.root_key()?;
+        self.key_case()?;
+        self.row_index()?;
+        self.indent()?;
+        self.flush_rows()?;
+        self.field_separator()?;
+        self.record_separator()?;
+        self.line_ending()?;
+        self.final_newline()?;
+        if let Some(template) = self.get("template") {
+            template.parse::<Template>()?;
+        }
+        Ok(())
+    }
+}
+
+impl FromIterator<(String, String)> for FormatOptions {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        FormatOptions {
+            options: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(pairs: &[(&str, &str)]) -> FormatOptions {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_typed_options() {
+        let options = options(&[("delimiter", ";"), ("quote", "always"), ("flush_rows", "5")]);
+        assert_eq!(options.delimiter().unwrap(), Some(b';'));
+        assert!(matches!(
+            options.quote_style().unwrap(),
+            Some(QuoteStyle::Always)
+        ));
+        assert_eq!(options.flush_rows().unwrap(), 5);
+        assert!(options.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(options(&[("delimiter", ";;")]).validate().is_err());
+        assert!(options(&[("quote", "sometimes")]).validate().is_err());
+        assert!(options(&[("flush_rows", "0")]).validate().is_err());
+        assert!(options(&[("null_aware", "maybe")]).validate().is_err());
+    }
+
+    #[test]
+    fn lists_unknown_keys() {
+        let options = options(&[("delimiter", ";"), ("zeta", "1"), ("alpha", "2")]);
+        assert_eq!(options.unknown_keys(&["delimiter"]), vec!["alpha", "zeta"]);
+    }
+
+    #[test]
+    fn unescapes_separators() {
+        let options = options(&[("field_separator", "\\t|"), ("record_separator", "\\r\\n")]);
+        assert_eq!(options.field_separator().unwrap().as_deref(), Some("\t|"));
+        assert_eq!(options.record_separator().unwrap().as_deref(), Some("\r\n"));
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::Context;
+use arboard::Clipboard;
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+use arboard::SetExtLinux;
+
+/// Puts text on the system clipboard. Fails with a hint when there is no clipboard to use, as on
+/// a headless server without a display.
+pub fn copy(text: String) -> anyhow::Result<()> {
+    let mut clipboard =
+        Clipboard::new().context("couldn't open the system clipboard (is a display available?)")?;
+    let set = clipboard.set();
+    // X11 and Wayland clipboards are served by the process that set them, so the text would
+    // vanish when we exit. Keep serving it until another program replaces it.
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    ))]
+    let set = set.wait();
+    set.text(text)
+        .context("couldn't write to the system clipboard")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn copies_text_where_a_display_is_available() {
+        // Best effort: only X11 is built in, so this needs an X server (XWayland will do).
+        if cfg!(target_os = "linux") && env::var_os("DISPLAY").is_none() {
+            return;
+        }
+        let text = "id\tname\n1\tAda\n";
+        // On Linux, copy() serves the text until another program takes the clipboard over.
+        let copier = thread::spawn(move || copy(text.to_string()));
+
+        let mut clipboard = Clipboard::new().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let copied = loop {
+            let copied = clipboard.get_text().unwrap_or_default();
+            if copied == text || copier.is_finished() || Instant::now() > deadline {
+                break copied;
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+        // Taking the clipboard over lets the copying thread finish.
+        clipboard.set_text("").unwrap();
+        copier.join().unwrap().unwrap();
+        assert_eq!(copied, text);
+    }
+
+    #[test]
+    fn explains_a_missing_display() {
+        // With a display, copying would wait for another program to take the clipboard over.
+        if cfg!(not(target_os = "linux"))
+            || env::var_os("DISPLAY").is_some()
+            || env::var_os("WAYLAND_DISPLAY").is_some()
+        {
+            return;
+        }
+        let err = copy("id\n1\n".to_string()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "couldn't open the system clipboard (is a display available?)"
+        );
+    }
+}
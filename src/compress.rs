@@ -0,0 +1,153 @@
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_extension(extension: &str) -> Option<Compression> {
+        match extension {
+            #[cfg(feature = "zstd")]
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    /// Clamps a user supplied level into the range the codec accepts, or returns its default level.
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    pub fn level(&self, level: Option<i32>) -> i32 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let range = zstd::compression_level_range();
+                level
+                    .map(|level| level.clamp(*range.start(), *range.end()))
+                    .unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL)
+            }
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            #[cfg(feature = "zstd")]
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            other => Err(anyhow::anyhow!("unsupported compression: {}", other)),
+        }
+    }
+}
+
+pub enum Encoder<W: Write> {
+    Plain(W),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    pub fn new(output: W, compression: Compression, level: Option<i32>) -> io::Result<Self> {
+        match compression {
+            Compression::None => Ok(Encoder::Plain(output)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(Encoder::Zstd(zstd::Encoder::new(
+                output,
+                compression.level(level),
+            )?)),
+        }
+    }
+
+    /// Writes any trailing compressed frames and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Plain(mut output) => {
+                output.flush()?;
+                Ok(output)
+            }
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(output) => output.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(output) => output.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_output_is_unchanged() {
+        let mut encoder = Encoder::new(Vec::new(), Compression::None, None).unwrap();
+        encoder.write_all(b"id,name\n1,a\n").unwrap();
+        assert_eq!(encoder.finish().unwrap(), b"id,name\n1,a\n");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trips_csv() {
+        let csv = "id,name\n1,caf\u{e9}\n2,\"a,b\"\n".repeat(100);
+        let mut encoder = Encoder::new(Vec::new(), Compression::Zstd, Some(19)).unwrap();
+        encoder.write_all(csv.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < csv.len());
+        assert_eq!(
+            zstd::decode_all(compressed.as_slice()).unwrap(),
+            csv.as_bytes()
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_levels_are_clamped() {
+        let range = zstd::compression_level_range();
+        assert_eq!(Compression::Zstd.level(Some(1000)), *range.end());
+        assert_eq!(Compression::Zstd.level(Some(-1_000_000)), *range.start());
+        assert_eq!(
+            Compression::Zstd.level(None),
+            zstd::DEFAULT_COMPRESSION_LEVEL
+        );
+    }
+
+    #[test]
+    fn parses_compression_names() {
+        assert_eq!("none".parse::<Compression>().unwrap(), Compression::None);
+        #[cfg(feature = "zstd")]
+        assert_eq!("ZSTD".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert!("gzip".parse::<Compression>().is_err());
+    }
+}
@@ -1,18 +1,165 @@
 use std::io::Write;
 
-use csv::{QuoteStyle, WriterBuilder};
+use csv::{QuoteStyle, Terminator, WriterBuilder};
 
-pub fn write<W>(rows: Vec<Vec<String>>, output: W) -> anyhow::Result<()>
+use crate::{
+    cli::FormatOptions,
+    delimited::{self, FinalNewline},
+    interrupt::is_interrupted,
+};
+
+pub const OPTIONS: &[&str] = &[
+    "delimiter",
+    "quote",
+    "flush_rows",
+    "field_separator",
+    "record_separator",
+    "line_ending",
+    "final_newline",
+    "echo_query",
+];
+pub const DEFAULT_DELIMITER: u8 = b',';
+
+pub fn write<W>(
+    rows: Vec<Vec<Option<String>>>,
+    output: W,
+    options: &FormatOptions,
+) -> anyhow::Result<()>
 where
     W: Write,
+{
+    write_rows(rows, output, options, None)
+}
+
+/// Writes rows as they arrive, flushing the output every `flush_rows` rows so a crash or a
+/// reader tailing the file sees the rows written so far.
+pub fn write_streaming<I, W>(rows: I, output: W, options: &FormatOptions) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
+{
+    if delimited::is_custom(options) {
+        let delimiter = options.delimiter()?.unwrap_or(DEFAULT_DELIMITER);
+        return delimited::write_streaming(rows, output, options, delimiter);
+    }
+    write_rows(rows, output, options, Some(options.flush_rows()?))
+}
+
+fn write_rows<I, W>(
+    rows: I,
+    output: W,
+    options: &FormatOptions,
+    flush_rows: Option<usize>,
+) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
 {
     let mut wtr = WriterBuilder::new()
-        .quote_style(QuoteStyle::NonNumeric)
-        .from_writer(output);
+        .delimiter(options.delimiter()?.unwrap_or(DEFAULT_DELIMITER))
+        .quote_style(options.quote_style()?.unwrap_or(QuoteStyle::NonNumeric))
+        .terminator(match options.line_ending()? {
+            "\r\n" => Terminator::CRLF,
+            _ => Terminator::Any(b'\n'),
+        })
+        .flexible(true)
+        .from_writer(FinalNewline::new(output, options.final_newline()?));
 
-    for row in rows.iter() {
-        wtr.write_record(row)?;
+    for (index, row) in rows.into_iter().enumerate() {
+        if is_interrupted() {
+            break;
+        }
+        wtr.write_record(row.iter().map(|cell| cell.as_deref().unwrap_or_default()))?;
+        if flush_rows.is_some_and(|flush_rows| (index + 1) % flush_rows == 0) {
+            wtr.flush()?;
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    use super::*;
+
+    /// A writer whose contents can be inspected while the CSV writer still holds it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn rows() -> Vec<Vec<Option<String>>> {
+        vec![
+            vec![Some("id".to_string()), Some("name".to_string())],
+            vec![Some("1".to_string()), Some("a;b".to_string())],
+            vec![Some("2".to_string()), None],
+        ]
+    }
+
+    fn write_csv(options: &FormatOptions) -> String {
+        let mut output = Vec::new();
+        write_streaming(rows(), &mut output, options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_comma_separated_values() {
+        assert_eq!(
+            write_csv(&FormatOptions::default()),
+            "\"id\",\"name\"\n1,\"a;b\"\n2,\"\"\n"
+        );
+    }
+
+    #[test]
+    fn delimiter_option_changes_the_separator() {
+        let mut options = FormatOptions::default();
+        options.set("delimiter", ";");
+        options.set("quote", "necessary");
+        assert_eq!(write_csv(&options), "id;name\n1;\"a;b\"\n2;\n");
+    }
+
+    #[test]
+    fn flushes_every_flush_rows_rows() {
+        let buffer = SharedBuffer::default();
+        let mut options = FormatOptions::default();
+        options.set("flush_rows", "2");
+        options.set("quote", "necessary");
+
+        let observed = buffer.clone();
+        let rows = (0..5).map(|index| {
+            if index == 3 {
+                // The header and first row were flushed; the second is still buffered.
+                assert_eq!(observed.0.borrow().as_slice(), b"id\n1\n");
+            }
+            let cell = if index == 0 {
+                "id".to_string()
+            } else {
+                index.to_string()
+            };
+            vec![Some(cell)]
+        });
+        write_streaming(rows, buffer.clone(), &options).unwrap();
+        assert_eq!(buffer.0.borrow().as_slice(), b"id\n1\n2\n3\n4\n");
+    }
+
+    #[test]
+    fn line_ending_and_final_newline() {
+        let mut options = FormatOptions::default();
+        options.set("quote", "necessary");
+        options.set("line_ending", "crlf");
+        assert_eq!(write_csv(&options), "id,name\r\n1,a;b\r\n2,\r\n");
+        options.set("final_newline", "false");
+        assert_eq!(write_csv(&options), "id,name\r\n1,a;b\r\n2,");
+    }
+}
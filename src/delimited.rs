@@ -0,0 +1,165 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+use crate::{cli::FormatOptions, interrupt::is_interrupted};
+
+/// Whether the options ask for separators the `csv` crate can't produce, in which case the
+/// CSV and TSV writers hand over to [`write_streaming`].
+pub fn is_custom(options: &FormatOptions) -> bool {
+    options.get("field_separator").is_some() || options.get("record_separator").is_some()
+}
+
+/// Quotes a field when it contains a separator, a quote, or a line break, doubling any quotes
+/// inside it so the separator is preserved as data.
+fn quote_field<'a>(field: &'a str, field_separator: &str, record_separator: &str) -> Cow<'a, str> {
+    if field.contains(field_separator)
+        || field.contains(record_separator)
+        || field.contains(['"', '\r', '\n'])
+    {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Writes rows with arbitrary, possibly multi-byte, field and record separators (e.g. `|` and
+/// `\r\n` for mainframe feeds). Unset separators fall back to `default_field_separator` and
+/// the configured line ending.
+pub fn write_streaming<I, W>(
+    rows: I,
+    mut output: W,
+    options: &FormatOptions,
+    default_field_separator: u8,
+) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
+{
+    let field_separator = options
+        .field_separator()?
+        .unwrap_or_else(|| char::from(default_field_separator).to_string());
+    let record_separator = match options.record_separator()? {
+        Some(record_separator) => record_separator,
+        None => options.line_ending()?.to_string(),
+    };
+    let final_newline = options.final_newline()?;
+    let flush_rows = options.flush_rows()?;
+
+    let mut wrote_rows = false;
+    for (index, row) in rows.into_iter().enumerate() {
+        if is_interrupted() {
+            break;
+        }
+        let fields: Vec<Cow<str>> = row
+            .iter()
+            .map(|cell| {
+                quote_field(
+                    cell.as_deref().unwrap_or_default(),
+                    &field_separator,
+                    &record_separator,
+                )
+            })
+            .collect();
+        if index > 0 {
+            output.write_all(record_separator.as_bytes())?;
+        }
+        output.write_all(fields.join(field_separator.as_str()).as_bytes())?;
+        if (index + 1) % flush_rows == 0 {
+            output.flush()?;
+        }
+        wrote_rows = true;
+    }
+
+    if wrote_rows && final_newline {
+        output.write_all(record_separator.as_bytes())?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Passes writes through, except that when `keep` is false it holds back trailing line-ending
+/// bytes until more data follows, so the output doesn't end with a newline.
+pub struct FinalNewline<W> {
+    inner: W,
+    keep: bool,
+    pending: Vec<u8>,
+}
+
+impl<W> FinalNewline<W> {
+    pub fn new(inner: W, keep: bool) -> Self {
+        FinalNewline {
+            inner,
+            keep,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<W> Write for FinalNewline<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.keep {
+            return self.inner.write(buf);
+        }
+
+        let content_len = buf
+            .iter()
+            .rposition(|&byte| byte != b'\r' && byte != b'\n')
+            .map_or(0, |position| position + 1);
+        if content_len > 0 {
+            self.inner.write_all(&self.pending)?;
+            self.pending.clear();
+            self.inner.write_all(&buf[..content_len])?;
+        }
+        self.pending.extend_from_slice(&buf[content_len..]);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_delimited(pairs: &[(&str, &str)]) -> String {
+        let options: FormatOptions = pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let rows = vec![
+            vec![Some("id".to_string()), Some("name".to_string())],
+            vec![Some("1".to_string()), Some("a|b".to_string())],
+            vec![Some("2".to_string()), None],
+        ];
+        let mut output = Vec::new();
+        write_streaming(rows, &mut output, &options, b',').unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_pipe_delimited_output() {
+        assert_eq!(
+            write_delimited(&[("field_separator", "|")]),
+            "id|name\n1|\"a|b\"\n2|\n"
+        );
+    }
+
+    #[test]
+    fn writes_crlf_terminated_output() {
+        assert_eq!(
+            write_delimited(&[("field_separator", "||"), ("record_separator", "\\r\\n")]),
+            "id||name\r\n1||a|b\r\n2||\r\n"
+        );
+        assert_eq!(
+            write_delimited(&[("record_separator", "\\r\\n"), ("final_newline", "false")]),
+            "id,name\r\n1,a|b\r\n2,"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt::Write;
+
+/// The exit code when the output differs from the `COMPARE_TO` reference.
+pub const EXIT_OUTPUT_CHANGED: i32 = 3;
+
+/// Lines of unchanged context around each change, as in `diff -u`.
+const CONTEXT: usize = 3;
+
+/// Above this many line pairs, the changed region is shown as removed and re-added in full rather
+/// than aligned line by line, which would need a table of that size.
+const MAX_ALIGNED_PAIRS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Compares two texts line by line and returns a unified diff of them, or `None` if they are
+/// identical. Line endings are part of each line, so a change from `\n` to `\r\n` shows up as a
+/// changed line.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = edit_script(&old_lines, &new_lines);
+
+    let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+    // Each op with the number of old and new lines before it.
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for op in &ops {
+        positions.push((old_pos, new_pos));
+        match op {
+            Op::Equal => (old_pos, new_pos) = (old_pos + 1, new_pos + 1),
+            Op::Delete => old_pos += 1,
+            Op::Insert => new_pos += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| ops[i] != Op::Equal).collect();
+    let mut index = 0;
+    while index < changes.len() {
+        let start = changes[index].saturating_sub(CONTEXT);
+        let mut end = changes[index];
+        while index + 1 < changes.len() && changes[index + 1] - end <= 2 * CONTEXT + 1 {
+            index += 1;
+            end = changes[index];
+        }
+        let end = (end + CONTEXT + 1).min(ops.len());
+        index += 1;
+
+        let old_len = ops[start..end]
+            .iter()
+            .filter(|op| **op != Op::Insert)
+            .count();
+        let new_len = ops[start..end]
+            .iter()
+            .filter(|op| **op != Op::Delete)
+            .count();
+        let (old_start, new_start) = positions[start];
+        let _ = writeln!(
+            diff,
+            "@@ -{},{} +{},{} @@",
+            old_start + usize::from(old_len > 0),
+            old_len,
+            new_start + usize::from(new_len > 0),
+            new_len
+        );
+        for (op, &(old_pos, new_pos)) in ops[start..end].iter().zip(&positions[start..end]) {
+            let (prefix, line) = match op {
+                Op::Equal => (' ', old_lines[old_pos]),
+                Op::Delete => ('-', old_lines[old_pos]),
+                Op::Insert => ('+', new_lines[new_pos]),
+            };
+            diff.push(prefix);
+            diff.push_str(line);
+            if !line.ends_with('\n') {
+                diff.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    Some(diff)
+}
+
+/// The operations turning `old` into `new`, aligning the lines of their longest common
+/// subsequence between any common prefix and suffix.
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut ops = vec![Op::Equal; prefix];
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_ALIGNED_PAIRS {
+        ops.extend(vec![Op::Delete; old_middle.len()]);
+        ops.extend(vec![Op::Insert; new_middle.len()]);
+    } else {
+        // lengths[i][j] is the LCS length of old_middle[i..] and new_middle[j..].
+        let width = new_middle.len() + 1;
+        let mut lengths = vec![0u32; (old_middle.len() + 1) * width];
+        for i in (0..old_middle.len()).rev() {
+            for j in (0..new_middle.len()).rev() {
+                lengths[i * width + j] = if old_middle[i] == new_middle[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old_middle.len() && j < new_middle.len() {
+            if old_middle[i] == new_middle[j] {
+                ops.push(Op::Equal);
+                (i, j) = (i + 1, j + 1);
+            } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+                ops.push(Op::Delete);
+                i += 1;
+            } else {
+                ops.push(Op::Insert);
+                j += 1;
+            }
+        }
+        ops.extend(vec![Op::Delete; old_middle.len() - i]);
+        ops.extend(vec![Op::Insert; new_middle.len() - j]);
+    }
+    ops.extend(vec![Op::Equal; suffix]);
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_texts_have_no_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), None);
+    }
+
+    #[test]
+    fn shows_changes_with_context() {
+        let old = "id\n1\n2\n3\n4\n5\n6\n7\n8\n";
+        let new = "id\n1\n2\n3\n4\nfive\n6\n7\n8\n";
+        assert_eq!(
+            unified_diff(old, new, "expected.csv", "output").unwrap(),
+            "--- expected.csv\n+++ output\n@@ -3,7 +3,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn counts_an_empty_side_from_zero() {
+        assert_eq!(
+            unified_diff("", "id\n1\n", "expected.csv", "output").unwrap(),
+            "--- expected.csv\n+++ output\n@@ -0,0 +1,2 @@\n+id\n+1\n"
+        );
+    }
+
+    #[test]
+    fn marks_a_missing_final_newline() {
+        assert_eq!(
+            unified_diff("id\n1\n", "id\n1", "old", "new").unwrap(),
+            "--- old\n+++ new\n@@ -1,2 +1,2 @@\n id\n-1\n+1\n\\ No newline at end of file\n"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use encoding_rs::{EncoderResult, WINDOWS_1252};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputEncoding {
+    Utf8,
+    Latin1,
+    Windows1252,
+}
+
+impl FromStr for OutputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf8" | "utf-8" => Ok(OutputEncoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(OutputEncoding::Latin1),
+            "windows-1252" | "cp1252" => Ok(OutputEncoding::Windows1252),
+            other => Err(anyhow::anyhow!("unsupported output encoding: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmappablePolicy {
+    Replace,
+    Error,
+}
+
+impl FromStr for UnmappablePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "replace" => Ok(UnmappablePolicy::Replace),
+            "error" => Ok(UnmappablePolicy::Error),
+            other => Err(anyhow::anyhow!("unsupported unmappable policy: {}", other)),
+        }
+    }
+}
+
+fn unmappable(c: char, encoding: OutputEncoding) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{:?} cannot be represented in {:?}", c, encoding),
+    )
+}
+
+pub fn encode(
+    text: &str,
+    encoding: OutputEncoding,
+    policy: UnmappablePolicy,
+) -> io::Result<Vec<u8>> {
+    match encoding {
+        OutputEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        OutputEncoding::Latin1 => text
+            .chars()
+            .map(|c| match u8::try_from(u32::from(c)) {
+                Ok(byte) => Ok(byte),
+                Err(_) if policy == UnmappablePolicy::Replace => Ok(b'?'),
+                Err(_) => Err(unmappable(c, encoding)),
+            })
+            .collect(),
+        OutputEncoding::Windows1252 => {
+            let mut encoder = WINDOWS_1252.new_encoder();
+            let mut output = Vec::with_capacity(text.len());
+            let mut buffer = [0u8; 1024];
+            let mut remaining = text;
+            loop {
+                let (result, read, written) =
+                    encoder.encode_from_utf8_without_replacement(remaining, &mut buffer, true);
+                output.extend_from_slice(&buffer[..written]);
+                remaining = &remaining[read..];
+                match result {
+                    EncoderResult::InputEmpty => return Ok(output),
+                    EncoderResult::OutputFull => {}
+                    EncoderResult::Unmappable(_) if policy == UnmappablePolicy::Replace => {
+                        output.push(b'?')
+                    }
+                    EncoderResult::Unmappable(c) => return Err(unmappable(c, encoding)),
+                }
+            }
+        }
+    }
+}
+
+/// Transcodes a UTF-8 byte stream into the target encoding, holding back any multi-byte
+/// character split across writes until the rest of it arrives.
+pub struct TranscodingWriter<W: Write> {
+    inner: W,
+    encoding: OutputEncoding,
+    policy: UnmappablePolicy,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> TranscodingWriter<W> {
+    pub fn new(inner: W, encoding: OutputEncoding, policy: UnmappablePolicy) -> Self {
+        TranscodingWriter {
+            inner,
+            encoding,
+            policy,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for TranscodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid_up_to = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        };
+
+        let text = std::str::from_utf8(&self.pending[..valid_up_to]).unwrap_or_default();
+        let encoded = encode(text, self.encoding, self.policy)?;
+        self.inner.write_all(&encoded)?;
+        self.pending.drain(..valid_up_to);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_latin1() {
+        let encoded = encode("café", OutputEncoding::Latin1, UnmappablePolicy::Error).unwrap();
+        assert_eq!(encoded, b"caf\xe9");
+    }
+
+    #[test]
+    fn encodes_windows_1252() {
+        let encoded = encode(
+            "€5 café",
+            OutputEncoding::Windows1252,
+            UnmappablePolicy::Error,
+        )
+        .unwrap();
+        assert_eq!(encoded, b"\x805 caf\xe9");
+    }
+
+    #[test]
+    fn unmappable_characters_fail_or_are_replaced() {
+        for encoding in [OutputEncoding::Latin1, OutputEncoding::Windows1252] {
+            let err = encode("a测试", encoding, UnmappablePolicy::Error).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                encode("a测试", encoding, UnmappablePolicy::Replace).unwrap(),
+                b"a??"
+            );
+        }
+    }
+
+    #[test]
+    fn writer_joins_characters_split_across_writes() {
+        let mut writer =
+            TranscodingWriter::new(Vec::new(), OutputEncoding::Latin1, UnmappablePolicy::Error);
+        let bytes = "café,é\n".as_bytes();
+        // Split inside the two-byte é.
+        writer.write_all(&bytes[..4]).unwrap();
+        writer.write_all(&bytes[4..]).unwrap();
+        assert_eq!(writer.inner, b"caf\xe9,\xe9\n");
+    }
+
+    #[test]
+    fn parses_encoding_names() {
+        assert_eq!(
+            "ISO_8859_1".parse::<OutputEncoding>().unwrap(),
+            OutputEncoding::Latin1
+        );
+        assert_eq!(
+            "cp1252".parse::<OutputEncoding>().unwrap(),
+            OutputEncoding::Windows1252
+        );
+        assert!("utf-16".parse::<OutputEncoding>().is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+/// Where CSV and TSV output shows each column's SQL type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypedHeader {
+    /// A second header row holding the types.
+    Row,
+    /// `name:type` in the header row itself.
+    Inline,
+}
+
+impl FromStr for TypedHeader {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "row" | "true" => Ok(TypedHeader::Row),
+            "inline" => Ok(TypedHeader::Inline),
+            other => Err(anyhow::anyhow!(
+                "unsupported typed header: {} (expected row or inline)",
+                other
+            )),
+        }
+    }
+}
+
+impl TypedHeader {
+    /// Adds the column types to a matrix whose first row is the header.
+    pub fn apply(&self, rows: &mut Vec<Vec<Option<String>>>, types: &[Option<String>]) {
+        match self {
+            TypedHeader::Row => {
+                if !rows.is_empty() {
+                    rows.insert(1, types.to_vec());
+                }
+            }
+            TypedHeader::Inline => {
+                if let Some(header) = rows.first_mut() {
+                    for (name, column_type) in header.iter_mut().zip(types) {
+                        if let (Some(name), Some(column_type)) = (name, column_type) {
+                            name.push(':');
+                            name.push_str(column_type);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits literal rows, one per line, into fields on the given delimiter. A field may be quoted
+/// as in CSV, so `"a,b"` keeps its delimiter and `""` inside the quotes stands for one quote.
+pub fn split_literal_rows(text: &str, delimiter: &str) -> Vec<Vec<Option<String>>> {
+    text.lines()
+        .map(|line| split_literal_row(line, delimiter))
+        .collect()
+}
+
+fn split_literal_row(line: &str, delimiter: &str) -> Vec<Option<String>> {
+    let mut fields = Vec::new();
+    let mut rest = line;
+    loop {
+        let mut field = String::new();
+        if let Some(quoted) = rest.strip_prefix('"') {
+            // An unterminated quote runs to the end of the line.
+            rest = "";
+            let mut chars = quoted.char_indices().peekable();
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '"' if chars.next_if(|&(_, c)| c == '"').is_some() => field.push('"'),
+                    '"' => {
+                        rest = &quoted[index + 1..];
+                        break;
+                    }
+                    c => field.push(c),
+                }
+            }
+        }
+        match rest.find(delimiter) {
+            Some(end) => {
+                field.push_str(&rest[..end]);
+                fields.push(Some(field));
+                rest = &rest[end + delimiter.len()..];
+            }
+            None => {
+                field.push_str(rest);
+                fields.push(Some(field));
+                return fields;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(row: &[Option<String>]) -> Vec<&str> {
+        row.iter().map(|field| field.as_deref().unwrap()).collect()
+    }
+
+    #[test]
+    fn splits_lines_on_the_delimiter() {
+        let rows = split_literal_rows("a,b,c\nTOTAL,,3", ",");
+        assert_eq!(rows.len(), 2);
+        assert_eq!(fields(&rows[0]), ["a", "b", "c"]);
+        assert_eq!(fields(&rows[1]), ["TOTAL", "", "3"]);
+    }
+
+    #[test]
+    fn quoted_fields_keep_delimiters_and_quotes() {
+        let rows = split_literal_rows("\"a,b\",\"say \"\"hi\"\"\",\"\",c", ",");
+        assert_eq!(fields(&rows[0]), ["a,b", "say \"hi\"", "", "c"]);
+    }
+
+    #[test]
+    fn multi_character_delimiters() {
+        let rows = split_literal_rows("\"x||y\"||z", "||");
+        assert_eq!(fields(&rows[0]), ["x||y", "z"]);
+    }
+
+    #[test]
+    fn unterminated_quotes_run_to_the_end_of_the_line() {
+        let rows = split_literal_rows("\"a,b", ",");
+        assert_eq!(fields(&rows[0]), ["a,b"]);
+    }
+
+    #[test]
+    fn typed_header_adds_types() {
+        let types = vec![Some("INT".to_string()), None];
+        let mut rows = vec![vec![Some("id".to_string()), Some("name".to_string())]];
+        TypedHeader::Inline.apply(&mut rows, &types);
+        assert_eq!(fields(&rows[0]), ["id:INT", "name"]);
+
+        let mut rows = vec![vec![Some("id".to_string())]];
+        TypedHeader::Row.apply(&mut rows, &types[..1]);
+        assert_eq!(
+            rows,
+            vec![vec![Some("id".to_string())], vec![Some("INT".to_string())]]
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static WRITING: AtomicBool = AtomicBool::new(false);
+
+/// Installs a SIGINT handler. Before output has started the process exits straight away; once
+/// writing has begun the writers are asked to stop at the next row so the output is finalized.
+pub fn install_handler() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        if !WRITING.load(Ordering::SeqCst) {
+            #[cfg(feature = "verbose")]
+            eprintln!("Interrupted.");
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    })
+}
+
+pub fn begin_writing() {
+    WRITING.store(true, Ordering::SeqCst);
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -1,24 +1,616 @@
-use std::{collections::HashMap, io::Write};
+use std::{cell::RefCell, io::Write};
 
-use mysql::serde_json::json;
+use mysql::{
+    serde::{
+        ser::{self, SerializeMap},
+        Serialize,
+    },
+    serde_json::{self, json, ser::PrettyFormatter, Map, Serializer, Value},
+};
 
-pub fn write<W>(rows: Vec<Vec<String>>, mut output: W) -> anyhow::Result<()>
+use crate::{cli::FormatOptions, interrupt::is_interrupted};
+
+pub const OPTIONS: &[&str] = &[
+    "null_aware",
+    "root_key",
+    "line_ending",
+    "final_newline",
+    "echo_query",
+    "pretty",
+    "indent",
+    "bool_columns",
+    "key_case",
+    "row_index",
+];
+
+/// The options newline-delimited JSON understands; it has no wrapper or pretty printing.
+pub const NDJSON_OPTIONS: &[&str] = &[
+    "null_aware",
+    "line_ending",
+    "final_newline",
+    "flush_rows",
+    "bool_columns",
+    "key_case",
+    "row_index",
+];
+
+/// How column names are cased when used as JSON keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCase {
+    AsIs,
+    /// `user_id`
+    Snake,
+    /// `userId`
+    Camel,
+}
+
+impl KeyCase {
+    pub fn apply(&self, name: &str) -> String {
+        match self {
+            KeyCase::AsIs => name.to_string(),
+            KeyCase::Snake => split_words(name)
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<String>>()
+                .join("_"),
+            KeyCase::Camel => split_words(name)
+                .iter()
+                .enumerate()
+                .map(|(index, word)| {
+                    let word = word.to_lowercase();
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) if index > 0 => first.to_uppercase().chain(chars).collect(),
+                        _ => word,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Splits a name into words at separators (`_`, `-`, spaces), at lower-to-upper case changes
+/// (`userId`), and before the last capital of an acronym followed by lowercase (`HTTPServer`
+/// is `HTTP` and `Server`). Digits stay with the word they follow.
+fn split_words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if matches!(c, '_' | '-' | ' ') {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let previous = chars[index - 1];
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_is_lower)
+            {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Types a single value by its SQL column type: NULL becomes `null`, integer and floating-point
+/// columns become numbers, and `JSON` columns are embedded as parsed JSON. `DECIMAL` stays a
+/// string, since a JSON number would be read back as a float and lose its exact digits. Anything
+/// else, or a value that doesn't parse as its type claims, stays a string.
+pub fn typed_value(cell: Option<&str>, sql_type: &str) -> Value {
+    let Some(cell) = cell else {
+        return Value::Null;
+    };
+    let parsed = match sql_type {
+        "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" | "YEAR" => cell
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| cell.parse::<u64>().map(Value::from))
+            .ok(),
+        "FLOAT" | "DOUBLE" => cell
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        "JSON" => serde_json::from_str(cell).ok(),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| Value::from(cell))
+}
+
+/// Turns rows into JSON objects keyed by their (cased) column names, typing booleans and NULL as
+/// the options ask.
+struct RowEncoder<'a> {
+    #[cfg(feature = "verbose")]
+    headers: Vec<String>,
+    keys: Vec<String>,
+    row_index: Option<&'a str>,
+    null_aware: bool,
+    bool_columns: Vec<bool>,
+    #[cfg(feature = "verbose")]
+    warned: Vec<bool>,
+}
+
+impl<'a> RowEncoder<'a> {
+    fn new(header_row: Vec<Option<String>>, options: &'a FormatOptions) -> anyhow::Result<Self> {
+        let headers: Vec<String> = header_row
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect();
+        let key_case = options.key_case()?;
+        let keys: Vec<String> = headers
+            .iter()
+            .map(|header| key_case.apply(header))
+            .collect();
+        for (index, key) in keys.iter().enumerate() {
+            if let Some(other) = keys[..index].iter().position(|other| other == key) {
+                if headers[other] != headers[index] {
+                    anyhow::bail!(
+                        "columns {} and {} both become the JSON key {}",
+                        headers[other],
+                        headers[index],
+                        key
+                    );
+                }
+            }
+        }
+        let row_index = options.row_index()?;
+        if let Some(position) =
+            row_index.and_then(|row_index| keys.iter().position(|key| key == row_index))
+        {
+            anyhow::bail!(
+                "the row index key {} collides with column {}; choose another JSON_ROW_INDEX",
+                keys[position],
+                headers[position]
+            );
+        }
+        let mut bool_columns = vec![false; headers.len()];
+        for column in options.bool_columns() {
+            match headers.iter().position(|header| header == column) {
+                Some(index) => bool_columns[index] = true,
+                None => anyhow::bail!("bool column {} does not exist in the result", column),
+            }
+        }
+
+        Ok(RowEncoder {
+            #[cfg(feature = "verbose")]
+            warned: vec![false; headers.len()],
+            #[cfg(feature = "verbose")]
+            headers,
+            keys,
+            row_index,
+            null_aware: options.flag("null_aware")?,
+            bool_columns,
+        })
+    }
+
+    fn encode(&mut self, row_number: u64, row: Vec<Option<String>>) -> Map<String, Value> {
+        let mut item = Map::new();
+        if let Some(row_index) = self.row_index {
+            item.insert(row_index.to_string(), Value::from(row_number));
+        }
+        for (index, (key, cell)) in self.keys.iter().zip(row).enumerate() {
+            let value = match cell {
+                None if self.null_aware => Value::Null,
+                None => Value::from(""),
+                Some(cell) if self.bool_columns[index] => match cell.as_str() {
+                    "0" => Value::Bool(false),
+                    "1" => Value::Bool(true),
+                    _ => {
+                        #[cfg(feature = "verbose")]
+                        if !std::mem::replace(&mut self.warned[index], true) {
+                            eprintln!(
+                                "Column {} has a value other than 0 or 1 ({}), writing it unchanged.",
+                                self.headers[index], cell
+                            );
+                        }
+                        Value::from(cell)
+                    }
+                },
+                Some(cell) => Value::from(cell),
+            };
+            item.insert(key.clone(), value);
+        }
+        item
+    }
+}
+
+/// Writes the rows as one JSON document, `{"<root_key>": [...]}` plus any echoed query under
+/// `meta`. The objects are written as the rows arrive, so the document is only complete once the
+/// rows run out.
+pub fn write<I, W>(rows: I, mut output: W, options: &FormatOptions) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
+{
+    let mut rows = rows.into_iter();
+    let header_row = match rows.next() {
+        Some(header_row) => header_row,
+        None => panic!("No header row found"),
+    };
+    let document = Document {
+        root_key: options.root_key()?,
+        rows: RowArray {
+            rows: RefCell::new(rows),
+            encoder: RefCell::new(RowEncoder::new(header_row, options)?),
+        },
+        meta: options
+            .get("echo_query")
+            .map(|query| json!({ "query": query })),
+    };
+    match options.indent()? {
+        None => serde_json::to_writer(&mut output, &document)?,
+        Some(indent) => {
+            let mut serializer =
+                Serializer::with_formatter(&mut output, PrettyFormatter::with_indent(&indent));
+            document.serialize(&mut serializer)?;
+        }
+    }
+    if options.final_newline()? {
+        output.write_all(options.line_ending()?.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// The top-level object, with its keys in the sorted order a `Value` map would give them.
+struct Document<'a, I> {
+    root_key: &'a str,
+    rows: RowArray<'a, I>,
+    meta: Option<Value>,
+}
+
+impl<I> Serialize for Document<'_, I>
+where
+    I: Iterator<Item = Vec<Option<String>>>,
+{
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1 + usize::from(self.meta.is_some())))?;
+        let meta_first = self.root_key > "meta";
+        if let Some(meta) = self.meta.as_ref().filter(|_| meta_first) {
+            map.serialize_entry("meta", meta)?;
+        }
+        map.serialize_entry(self.root_key, &self.rows)?;
+        if let Some(meta) = self.meta.as_ref().filter(|_| !meta_first) {
+            map.serialize_entry("meta", meta)?;
+        }
+        map.end()
+    }
+}
+
+/// The rows as a JSON array, encoded one at a time as the serializer asks for them.
+struct RowArray<'a, I> {
+    rows: RefCell<I>,
+    encoder: RefCell<RowEncoder<'a>>,
+}
+
+impl<I> Serialize for RowArray<'_, I>
+where
+    I: Iterator<Item = Vec<Option<String>>>,
+{
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut rows = self.rows.borrow_mut();
+        let mut encoder = self.encoder.borrow_mut();
+        serializer.collect_seq(
+            (1u64..)
+                .zip(&mut *rows)
+                .take_while(|_| !is_interrupted())
+                .map(|(row_number, row)| encoder.encode(row_number, row)),
+        )
+    }
+}
+
+/// Writes newline-delimited JSON: one object per row, each on its own line, as they arrive. The
+/// lines are separated by the configured line ending, which also ends the file unless
+/// `final_newline` is off. An empty result is an empty file.
+pub fn write_lines<I, W>(rows: I, mut output: W, options: &FormatOptions) -> anyhow::Result<()>
 where
+    I: IntoIterator<Item = Vec<Option<String>>>,
     W: Write,
 {
-    let headers = match rows.first() {
-        Some(header_row) => header_row.to_owned(),
+    let mut rows = rows.into_iter();
+    let header_row = match rows.next() {
+        Some(header_row) => header_row,
         None => panic!("No header row found"),
     };
-    let mut results: Vec<HashMap<String, String>> = Vec::new();
+    let mut encoder = RowEncoder::new(header_row, options)?;
+    let line_ending = options.line_ending()?;
+    let flush_rows = options.flush_rows()?;
 
-    for row in rows.into_iter().skip(1) {
-        let item: HashMap<String, String> =
-            headers.clone().into_iter().zip(row.into_iter()).collect();
-        results.push(item);
+    let mut wrote_rows = false;
+    for (row_number, row) in (1u64..).zip(rows) {
+        if is_interrupted() {
+            break;
+        }
+        if wrote_rows {
+            output.write_all(line_ending.as_bytes())?;
+        }
+        serde_json::to_writer(&mut output, &encoder.encode(row_number, row))?;
+        if row_number % flush_rows as u64 == 0 {
+            output.flush()?;
+        }
+        wrote_rows = true;
     }
 
-    let result: String = json!({ "data": results }).to_string();
-    output.write_all(result.as_bytes())?;
+    if wrote_rows && options.final_newline()? {
+        output.write_all(line_ending.as_bytes())?;
+    }
+    output.flush()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(pairs: &[(&str, &str)]) -> FormatOptions {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn to_json(rows: Vec<Vec<Option<&str>>>, options: &FormatOptions) -> Value {
+        let rows = rows.into_iter().map(|row| {
+            row.into_iter()
+                .map(|cell| cell.map(str::to_string))
+                .collect()
+        });
+        let mut output = Vec::new();
+        write(rows, &mut output, options).unwrap();
+        serde_json::from_slice(&output).unwrap()
+    }
+
+    #[test]
+    fn types_values_by_their_sql_type() {
+        assert_eq!(typed_value(Some("42"), "BIGINT"), json!(42));
+        assert_eq!(
+            typed_value(Some("18446744073709551615"), "BIGINT"),
+            json!(u64::MAX)
+        );
+        assert_eq!(typed_value(Some("2.5"), "DOUBLE"), json!(2.5));
+        assert_eq!(typed_value(Some("{\"a\":1}"), "JSON"), json!({ "a": 1 }));
+        assert_eq!(typed_value(None, "INT"), Value::Null);
+        assert_eq!(typed_value(Some("abc"), "INT"), json!("abc"));
+        assert_eq!(typed_value(Some("2024-01-02"), "DATE"), json!("2024-01-02"));
+    }
+
+    #[test]
+    fn decimals_keep_their_digits() {
+        let decimal = "12345678901234567890.123456789";
+        assert_eq!(typed_value(Some(decimal), "DECIMAL"), json!(decimal));
+        assert_eq!(typed_value(Some("0.10"), "DECIMAL").to_string(), "\"0.10\"");
+    }
+
+    #[test]
+    fn row_index_numbers_each_object() {
+        let rows = vec![vec![Some("name")], vec![Some("a")], vec![Some("b")]];
+        assert_eq!(
+            to_json(rows.clone(), &options(&[("row_index", "true")])),
+            json!({ "data": [{ "_row": 1, "name": "a" }, { "_row": 2, "name": "b" }] })
+        );
+        assert_eq!(
+            to_json(rows.clone(), &options(&[("row_index", "n")])),
+            json!({ "data": [{ "n": 1, "name": "a" }, { "n": 2, "name": "b" }] })
+        );
+
+        let mut output = Vec::new();
+        let rows = rows.into_iter().map(|row| {
+            row.into_iter()
+                .map(|cell| cell.map(str::to_string))
+                .collect()
+        });
+        write_lines(rows, &mut output, &options(&[("row_index", "yes")])).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"_row\":1,\"name\":\"a\"}\n{\"_row\":2,\"name\":\"b\"}\n"
+        );
+        assert!(options(&[("row_index", "")]).row_index().is_err());
+    }
+
+    #[test]
+    fn row_index_must_not_collide_with_a_column() {
+        let rows = vec![vec![Some("_row".to_string())], vec![Some("1".to_string())]];
+        let err = write(rows, Vec::new(), &options(&[("row_index", "true")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the row index key _row collides with column _row; choose another JSON_ROW_INDEX"
+        );
+    }
+
+    #[test]
+    fn null_aware_keeps_null_apart_from_empty_strings() {
+        let rows = vec![vec![Some("missing"), Some("blank")], vec![None, Some("")]];
+        assert_eq!(
+            to_json(rows.clone(), &options(&[("null_aware", "true")])),
+            json!({ "data": [{ "missing": null, "blank": "" }] })
+        );
+        assert_eq!(
+            to_json(rows, &FormatOptions::default()),
+            json!({ "data": [{ "missing": "", "blank": "" }] })
+        );
+    }
+
+    #[test]
+    fn echoed_query_keeps_the_keys_sorted() {
+        let to_text = |options: &FormatOptions| {
+            let mut output = Vec::new();
+            let rows = vec![vec![Some("id".to_string())], vec![Some("1".to_string())]];
+            write(rows, &mut output, options).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(
+            to_text(&options(&[("echo_query", "SELECT 1")])),
+            "{\"data\":[{\"id\":\"1\"}],\"meta\":{\"query\":\"SELECT 1\"}}\n"
+        );
+        assert_eq!(
+            to_text(&options(&[
+                ("echo_query", "SELECT 1"),
+                ("root_key", "results")
+            ])),
+            "{\"meta\":{\"query\":\"SELECT 1\"},\"results\":[{\"id\":\"1\"}]}\n"
+        );
+    }
+
+    #[test]
+    fn root_key_renames_the_wrapper() {
+        let rows = vec![vec![Some("id")], vec![Some("1")]];
+        assert_eq!(
+            to_json(rows.clone(), &options(&[("root_key", "results")])),
+            json!({ "results": [{ "id": "1" }] })
+        );
+        assert_eq!(
+            to_json(rows, &FormatOptions::default()),
+            json!({ "data": [{ "id": "1" }] })
+        );
+        assert!(options(&[("root_key", "1st")]).root_key().is_err());
+    }
+
+    #[test]
+    fn bool_columns_map_zero_and_one() {
+        let options = options(&[("bool_columns", "active, deleted")]);
+        let json = to_json(
+            vec![
+                vec![Some("id"), Some("active"), Some("deleted")],
+                vec![Some("1"), Some("1"), Some("0")],
+                vec![Some("0"), Some("2"), None],
+            ],
+            &options,
+        );
+        assert_eq!(
+            json,
+            serde_json::json!({"data": [
+                {"id": "1", "active": true, "deleted": false},
+                {"id": "0", "active": "2", "deleted": ""},
+            ]})
+        );
+    }
+
+    #[test]
+    fn bool_columns_must_exist() {
+        let mut output = Vec::new();
+        let rows = vec![vec![Some("id".to_string())]];
+        let err = write(rows, &mut output, &options(&[("bool_columns", "active")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bool column active does not exist in the result"
+        );
+    }
+
+    #[test]
+    fn cases_keys() {
+        for (name, snake, camel) in [
+            ("userId", "user_id", "userId"),
+            ("USER_ID", "user_id", "userId"),
+            ("HTTPServer", "http_server", "httpServer"),
+            ("order-total 2", "order_total_2", "orderTotal2"),
+            ("address2Line", "address2_line", "address2Line"),
+        ] {
+            assert_eq!(KeyCase::Snake.apply(name), snake, "{}", name);
+            assert_eq!(KeyCase::Camel.apply(name), camel, "{}", name);
+            assert_eq!(KeyCase::AsIs.apply(name), name);
+        }
+    }
+
+    #[test]
+    fn cased_keys_must_not_collide() {
+        let options = options(&[("key_case", "snake")]);
+        let json = to_json(
+            vec![
+                vec![Some("userId"), Some("Name")],
+                vec![Some("1"), Some("a")],
+            ],
+            &options,
+        );
+        assert_eq!(
+            json,
+            serde_json::json!({"data": [{"user_id": "1", "name": "a"}]})
+        );
+
+        let rows = vec![vec![
+            Some("userId".to_string()),
+            Some("user_id".to_string()),
+        ]];
+        let err = write(rows, &mut Vec::new(), &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "columns userId and user_id both become the JSON key user_id"
+        );
+    }
+
+    fn to_lines(rows: Vec<Vec<Option<&str>>>, options: &FormatOptions) -> String {
+        let rows = rows.into_iter().map(|row| {
+            row.into_iter()
+                .map(|cell| cell.map(str::to_string))
+                .collect()
+        });
+        let mut output = Vec::new();
+        write_lines(rows, &mut output, options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_one_object_per_line() {
+        let rows = vec![
+            vec![Some("id"), Some("name")],
+            vec![Some("1"), Some("a")],
+            vec![Some("2"), None],
+        ];
+        assert_eq!(
+            to_lines(rows.clone(), &FormatOptions::default()),
+            "{\"id\":\"1\",\"name\":\"a\"}\n{\"id\":\"2\",\"name\":\"\"}\n"
+        );
+        assert_eq!(
+            to_lines(
+                rows,
+                &options(&[("line_ending", "crlf"), ("final_newline", "false")])
+            ),
+            "{\"id\":\"1\",\"name\":\"a\"}\r\n{\"id\":\"2\",\"name\":\"\"}"
+        );
+        assert_eq!(
+            to_lines(vec![vec![Some("id")]], &FormatOptions::default()),
+            ""
+        );
+    }
+
+    #[test]
+    fn json_document_honors_final_newline() {
+        let rows = || [vec![Some("id".to_string())], vec![Some("1".to_string())]];
+        let mut output = Vec::new();
+        write(rows(), &mut output, &options(&[("line_ending", "crlf")])).unwrap();
+        assert_eq!(output, b"{\"data\":[{\"id\":\"1\"}]}\r\n");
+
+        let mut output = Vec::new();
+        write(rows(), &mut output, &options(&[("final_newline", "false")])).unwrap();
+        assert_eq!(output, b"{\"data\":[{\"id\":\"1\"}]}");
+    }
+
+    #[test]
+    fn indents_by_four_spaces() {
+        let rows = [
+            vec![Some("id".to_string()), Some("name".to_string())],
+            vec![Some("1".to_string()), Some("a".to_string())],
+        ];
+        let mut output = Vec::new();
+        write(rows, &mut output, &options(&[("indent", "4")])).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{
+    "data": [
+        {
+            "id": "1",
+            "name": "a"
+        }
+    ]
+}
+"#
+        );
+    }
+}
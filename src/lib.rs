@@ -1,36 +1,506 @@
-use std::{ffi::OsStr, path::Path};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    fs::{self, OpenOptions},
+    io,
+    path::Path,
+};
 
-use mysql::{from_value, Row};
+use mysql::{
+    consts::{ColumnFlags, ColumnType},
+    Column, Row, Value,
+};
 
+use crate::{
+    compress::Compression,
+    value::{CellFormatter, ValueFormatter},
+};
+
+pub mod cli;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod compress;
+pub mod connection;
 pub mod csv;
+pub mod delimited;
+pub mod diff;
+pub mod encoding;
+pub mod format;
+pub mod interrupt;
 pub mod json;
+pub mod limit;
+pub mod locale;
+pub mod manifest;
+pub mod params;
+pub mod progress;
+pub mod prompt;
+pub mod query_log;
+pub mod server;
+pub mod sql;
+#[cfg(feature = "ssh")]
+pub mod ssh;
+pub mod summary;
 pub mod tab;
+pub mod tee;
+pub mod template;
+pub mod timing;
+pub mod tls;
+pub mod transform;
+pub mod value;
 
-pub fn rows_to_strings(rows: Vec<Row>) -> anyhow::Result<Vec<Vec<String>>> {
-    let mut result_rows: Vec<Vec<String>> = Vec::new();
-    for row in rows.into_iter() {
+/// Converts rows into a matrix of text cells with the column names as the first row, rendering
+/// each value with `formatter`. SQL NULL is normally kept as `None` so writers can tell it apart
+/// from an empty string.
+pub fn rows_to_strings<F>(
+    rows: Vec<Row>,
+    strict_utf8: bool,
+    formatter: &F,
+) -> anyhow::Result<Vec<Vec<Option<String>>>>
+where
+    F: ValueFormatter,
+{
+    let mut result_rows: Vec<Vec<Option<String>>> = Vec::new();
+    for (index, row) in rows.into_iter().enumerate() {
         if result_rows.is_empty() {
-            let header_row: Vec<String> = row
+            let header_row: Vec<Option<String>> = row
                 .columns_ref()
-                .to_vec()
                 .iter()
-                .map(|column| column.name_str().to_string())
-                .collect::<Vec<String>>();
+                .map(|column| Some(column.name_str().to_string()))
+                .collect::<Vec<Option<String>>>();
             result_rows.push(header_row);
         }
 
-        let data_row: Vec<String> = row
-            .columns_ref()
-            .to_vec()
-            .iter()
-            .map(|column| from_value::<String>(row[column.name_str().as_ref()].to_owned()))
-            .collect::<Vec<String>>();
+        let mut data_row: Vec<Option<String>> = Vec::with_capacity(row.len());
+        for (column_index, column) in row.columns_ref().iter().enumerate() {
+            let value = row.as_ref(column_index).unwrap_or(&Value::NULL);
+            if strict_utf8 {
+                if let Value::Bytes(bytes) = value {
+                    if std::str::from_utf8(bytes).is_err() {
+                        anyhow::bail!(
+                            "row {}: column {} contains invalid UTF-8",
+                            index + 1,
+                            column.name_str()
+                        );
+                    }
+                }
+            }
+            data_row.push(formatter.format_column(value, column.column_type()));
+        }
         result_rows.push(data_row);
     }
 
     Ok(result_rows)
 }
 
+/// The SQL type name of each column in a row's result set, e.g. `INT`, `VARCHAR`, or
+/// `DATETIME`.
+pub fn column_types(row: &Row) -> Vec<String> {
+    row.columns_ref().iter().map(column_type_name).collect()
+}
+
+/// Describes a result set's columns as rows of name, SQL type, and whether the column is
+/// nullable (`YES` or `NO`, as in `information_schema`), with a header row.
+pub fn column_schema(columns: &[Column]) -> Vec<Vec<Option<String>>> {
+    let mut rows = vec![vec![
+        Some("name".to_string()),
+        Some("type".to_string()),
+        Some("nullable".to_string()),
+    ]];
+    for column in columns {
+        let nullable = !column.flags().contains(ColumnFlags::NOT_NULL_FLAG);
+        rows.push(vec![
+            Some(column.name_str().to_string()),
+            Some(column_type_name(column)),
+            Some(if nullable { "YES" } else { "NO" }.to_string()),
+        ]);
+    }
+    rows
+}
+
+fn column_type_name(column: &Column) -> String {
+    // Text and binary columns share the blob types; the binary character set tells them apart.
+    let binary = column.character_set() == 63;
+    let name = match column.column_type() {
+        ColumnType::MYSQL_TYPE_TINY => "TINYINT",
+        ColumnType::MYSQL_TYPE_SHORT => "SMALLINT",
+        ColumnType::MYSQL_TYPE_INT24 => "MEDIUMINT",
+        ColumnType::MYSQL_TYPE_LONG => "INT",
+        ColumnType::MYSQL_TYPE_LONGLONG => "BIGINT",
+        ColumnType::MYSQL_TYPE_DECIMAL | ColumnType::MYSQL_TYPE_NEWDECIMAL => "DECIMAL",
+        ColumnType::MYSQL_TYPE_TIMESTAMP | ColumnType::MYSQL_TYPE_TIMESTAMP2 => "TIMESTAMP",
+        ColumnType::MYSQL_TYPE_DATE | ColumnType::MYSQL_TYPE_NEWDATE => "DATE",
+        ColumnType::MYSQL_TYPE_TIME | ColumnType::MYSQL_TYPE_TIME2 => "TIME",
+        ColumnType::MYSQL_TYPE_DATETIME | ColumnType::MYSQL_TYPE_DATETIME2 => "DATETIME",
+        ColumnType::MYSQL_TYPE_VARCHAR | ColumnType::MYSQL_TYPE_VAR_STRING if binary => "VARBINARY",
+        ColumnType::MYSQL_TYPE_VARCHAR | ColumnType::MYSQL_TYPE_VAR_STRING => "VARCHAR",
+        ColumnType::MYSQL_TYPE_STRING if binary => "BINARY",
+        ColumnType::MYSQL_TYPE_STRING => "CHAR",
+        ColumnType::MYSQL_TYPE_TINY_BLOB
+        | ColumnType::MYSQL_TYPE_MEDIUM_BLOB
+        | ColumnType::MYSQL_TYPE_LONG_BLOB
+        | ColumnType::MYSQL_TYPE_BLOB
+            if binary =>
+        {
+            "BLOB"
+        }
+        ColumnType::MYSQL_TYPE_TINY_BLOB
+        | ColumnType::MYSQL_TYPE_MEDIUM_BLOB
+        | ColumnType::MYSQL_TYPE_LONG_BLOB
+        | ColumnType::MYSQL_TYPE_BLOB => "TEXT",
+        other => {
+            return format!("{:?}", other)
+                .trim_start_matches("MYSQL_TYPE_")
+                .to_string()
+        }
+    };
+    name.to_string()
+}
+
+/// Renders a value with the default [`CellFormatter`], writing SQL NULL as an empty string.
+pub fn mysql_value_to_string(value: &Value) -> String {
+    CellFormatter::default().format(value).unwrap_or_default()
+}
+
 pub fn get_extension_from_filename(filename: &str) -> Option<&str> {
     Path::new(filename).extension().and_then(OsStr::to_str)
 }
+
+pub fn get_format_extension_from_filename(filename: &str) -> Option<&str> {
+    match get_extension_from_filename(filename) {
+        Some(extension) if Compression::from_extension(extension).is_some() => {
+            let stem = &filename[..filename.len() - extension.len() - 1];
+            get_extension_from_filename(stem)
+        }
+        extension => extension,
+    }
+}
+
+/// The formats an output file can be written in.
+pub const FORMATS: [&str; 5] = ["csv", "tsv", "txt", "json", "ndjson"];
+
+/// Maps alternative names of a format onto the one in [`FORMATS`]: `jsonl` is `ndjson`.
+fn canonical_format(format: &str) -> &str {
+    match format {
+        "jsonl" => "ndjson",
+        format => format,
+    }
+}
+
+/// Parses an extension override map such as `txt=tsv,dat=csv`, so other extensions can select a
+/// format. Extensions are matched case-insensitively, and every target must be a known format.
+pub fn parse_extension_map(text: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut map = HashMap::new();
+    for entry in text
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (extension, format) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("expected extension=format, got {}", entry))?;
+        let extension = extension
+            .trim()
+            .trim_start_matches('.')
+            .to_ascii_lowercase();
+        let format = canonical_format(&format.trim().to_ascii_lowercase()).to_string();
+        if extension.is_empty() {
+            anyhow::bail!("missing extension in {}", entry);
+        }
+        if !FORMATS.contains(&format.as_str()) {
+            anyhow::bail!(
+                "unsupported format for .{}: {} (expected csv, tsv, txt, json or ndjson)",
+                extension,
+                format
+            );
+        }
+        map.insert(extension, format);
+    }
+    Ok(map)
+}
+
+/// The output format for a file: its format extension, mapped through `overrides` when listed
+/// there.
+pub fn get_output_format<'a>(
+    filename: &'a str,
+    overrides: &'a HashMap<String, String>,
+) -> Option<&'a str> {
+    let extension = get_format_extension_from_filename(filename)?;
+    match overrides.get(&extension.to_ascii_lowercase()) {
+        Some(format) => Some(format.as_str()),
+        None => Some(canonical_format(extension)),
+    }
+}
+
+/// Splits a format token such as `csv.zst` into its base format and compression, so a single
+/// setting can name both. A plain `csv` has no compression.
+pub fn parse_format_token(token: &str) -> anyhow::Result<(String, Compression)> {
+    let token = token.to_ascii_lowercase();
+    let (format, compression) = match token.split_once('.') {
+        Some((format, extension)) => match Compression::from_extension(extension) {
+            Some(compression) => (format, compression),
+            None => anyhow::bail!("unsupported compression in {}: {}", token, extension),
+        },
+        None => (token.as_str(), Compression::None),
+    };
+    let format = canonical_format(format);
+    if !FORMATS.contains(&format) {
+        anyhow::bail!(
+            "unsupported format: {} (expected csv, tsv, txt, json or ndjson, optionally followed by a \
+             compression extension such as .zst)",
+            format
+        );
+    }
+    Ok((format.to_string(), compression))
+}
+
+/// Inserts a zero-padded chunk number ahead of the file's format (and compression) extensions,
+/// e.g. `output.csv.zst` becomes `output-00001.csv.zst`.
+pub fn get_chunk_filename(filename: &str, index: usize) -> Option<String> {
+    get_tagged_filename(filename, &format!("{:05}", index))
+}
+
+/// Inserts a partition value ahead of the file's extensions, e.g. `output.csv` becomes
+/// `output-emea.csv`. Characters other than ASCII letters, digits, `-`, `_`, and `.` are
+/// replaced with `_`; SQL NULL becomes `NULL` and an empty value `EMPTY`. Without an extension
+/// the value is appended.
+pub fn get_partition_filename(filename: &str, value: Option<&str>) -> String {
+    let tag: String = match value {
+        None => "NULL".to_string(),
+        Some("") => "EMPTY".to_string(),
+        Some(value) => value
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect(),
+    };
+    get_tagged_filename(filename, &tag).unwrap_or_else(|| format!("{}-{}", filename, tag))
+}
+
+fn get_tagged_filename(filename: &str, tag: &str) -> Option<String> {
+    let format_extension = get_format_extension_from_filename(filename)?;
+    let mut suffix_len = format_extension.len() + 1;
+    if let Some(extension) = get_extension_from_filename(filename)
+        .filter(|extension| Compression::from_extension(extension).is_some())
+    {
+        suffix_len += extension.len() + 1;
+    }
+    let (stem, suffix) = filename.split_at(filename.len() - suffix_len);
+    Some(format!("{}-{}{}", stem, tag, suffix))
+}
+
+pub fn get_env_flag(name: &str) -> bool {
+    match env::var(name) {
+        Ok(val) => matches!(
+            val.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        ),
+        Err(_) => false,
+    }
+}
+
+/// Reads `<PREFIX>_<NAME>` when a prefix is given and that variable is set, falling back to the
+/// unprefixed `<NAME>`, so several configurations can share one environment.
+pub fn get_prefixed_env(prefix: Option<&str>, name: &str) -> Result<String, env::VarError> {
+    prefix
+        .map(|prefix| prefixed_env_name(prefix, name))
+        .and_then(|prefixed| env::var(prefixed).ok())
+        .map_or_else(|| env::var(name), Ok)
+}
+
+/// The prefixed name `<PREFIX>_<NAME>` of a variable; a trailing `_` on the prefix is optional.
+pub fn prefixed_env_name(prefix: &str, name: &str) -> String {
+    format!("{}_{}", prefix.trim_end_matches('_'), name)
+}
+
+/// Confirms the output file could be written, without truncating an existing file or leaving a
+/// new one behind.
+pub fn check_output_writable(filename: &str) -> anyhow::Result<()> {
+    let path = Path::new(filename);
+    if path.exists() {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|err| anyhow::anyhow!("{} is not writable: {}", filename, err))?;
+    } else {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|err| anyhow::anyhow!("{} cannot be created: {}", filename, err))?;
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Fails if `filename` is a symbolic link, checked without following it, so the output can't be
+/// redirected onto another file by a link planted at its path. A missing file is fine.
+pub fn refuse_symlink(filename: &str) -> io::Result<()> {
+    match fs::symlink_metadata(filename) {
+        Ok(metadata) if metadata.file_type().is_symlink() => Err(io::Error::other(format!(
+            "{} is a symbolic link, refusing to write through it",
+            filename
+        ))),
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use mysql_common_rows::row::new_row;
+
+    use super::*;
+
+    fn text_row(names: &[&str], values: Vec<Value>) -> Row {
+        let columns: Vec<Column> = names
+            .iter()
+            .map(|name| Column::new(ColumnType::MYSQL_TYPE_VAR_STRING).with_name(name.as_bytes()))
+            .collect();
+        new_row(values, Arc::from(columns))
+    }
+
+    #[test]
+    fn prefixed_variables_win() {
+        env::set_var("GD_TEST_PREFIX_BOTH", "unprefixed");
+        env::set_var("REPORTING_GD_TEST_PREFIX_BOTH", "prefixed");
+        env::set_var("GD_TEST_PREFIX_PLAIN", "unprefixed");
+
+        let prefix = Some("REPORTING_");
+        assert_eq!(
+            get_prefixed_env(prefix, "GD_TEST_PREFIX_BOTH").as_deref(),
+            Ok("prefixed")
+        );
+        assert_eq!(
+            get_prefixed_env(prefix, "GD_TEST_PREFIX_PLAIN").as_deref(),
+            Ok("unprefixed")
+        );
+        assert_eq!(
+            get_prefixed_env(None, "GD_TEST_PREFIX_BOTH").as_deref(),
+            Ok("unprefixed")
+        );
+        assert!(get_prefixed_env(prefix, "GD_TEST_PREFIX_UNSET").is_err());
+    }
+
+    #[test]
+    fn extension_map_overrides_formats() {
+        let overrides = parse_extension_map(" .DAT=csv, txt=tsv ,, log=JSONL").unwrap();
+        assert_eq!(overrides.len(), 3);
+        assert_eq!(get_output_format("out.dat", &overrides), Some("csv"));
+        assert_eq!(get_output_format("out.TXT", &overrides), Some("tsv"));
+        assert_eq!(get_output_format("out.log", &overrides), Some("ndjson"));
+        assert_eq!(get_output_format("out.json", &overrides), Some("json"));
+
+        let err = parse_extension_map("dat").unwrap_err();
+        assert_eq!(err.to_string(), "expected extension=format, got dat");
+        assert!(parse_extension_map("=csv").is_err());
+        let err = parse_extension_map("dat=xml").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("unsupported format for .dat: xml"));
+    }
+
+    #[test]
+    fn jsonl_is_ndjson() {
+        let overrides = HashMap::new();
+        assert_eq!(get_output_format("out.jsonl", &overrides), Some("ndjson"));
+        assert_eq!(
+            get_output_format("out.ndjson.zst", &overrides),
+            Some("ndjson")
+        );
+        assert_eq!(parse_format_token("jsonl").unwrap().0, "ndjson");
+    }
+
+    #[test]
+    fn parses_format_tokens() {
+        assert_eq!(
+            parse_format_token("CSV").unwrap(),
+            ("csv".to_string(), Compression::None)
+        );
+        #[cfg(feature = "zstd")]
+        assert_eq!(
+            parse_format_token("tsv.zst").unwrap(),
+            ("tsv".to_string(), Compression::Zstd)
+        );
+        let err = parse_format_token("csv.rar").unwrap_err();
+        assert_eq!(err.to_string(), "unsupported compression in csv.rar: rar");
+        let err = parse_format_token("xml").unwrap_err();
+        assert!(
+            err.to_string().starts_with("unsupported format: xml"),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_only_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file.csv");
+        let link = dir.path().join("link.csv");
+        fs::write(&file, "").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        assert!(refuse_symlink(file.to_str().unwrap()).is_ok());
+        assert!(refuse_symlink(dir.path().join("missing.csv").to_str().unwrap()).is_ok());
+        let err = refuse_symlink(link.to_str().unwrap()).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("is a symbolic link, refusing to write through it"));
+    }
+
+    #[test]
+    fn describes_columns() {
+        let columns = [
+            Column::new(ColumnType::MYSQL_TYPE_LONG)
+                .with_name(b"id")
+                .with_flags(ColumnFlags::NOT_NULL_FLAG | ColumnFlags::PRI_KEY_FLAG),
+            Column::new(ColumnType::MYSQL_TYPE_BLOB)
+                .with_name(b"payload")
+                .with_character_set(63),
+        ];
+        let expected: Vec<Vec<Option<String>>> = [
+            ["name", "type", "nullable"],
+            ["id", "INT", "NO"],
+            ["payload", "BLOB", "YES"],
+        ]
+        .iter()
+        .map(|row| row.iter().map(|cell| Some(cell.to_string())).collect())
+        .collect();
+        assert_eq!(column_schema(&columns), expected);
+    }
+
+    #[test]
+    fn strict_utf8_rejects_invalid_bytes() {
+        let rows = vec![
+            text_row(&["name"], vec![Value::Bytes(b"caf\xc3\xa9".to_vec())]),
+            text_row(&["name"], vec![Value::Bytes(b"caf\xe9".to_vec())]),
+        ];
+        let err = rows_to_strings(rows, true, &CellFormatter::default()).unwrap_err();
+        assert_eq!(err.to_string(), "row 2: column name contains invalid UTF-8");
+    }
+
+    #[test]
+    fn lenient_utf8_replaces_invalid_bytes() {
+        let rows = vec![
+            text_row(&["name"], vec![Value::Bytes(b"caf\xc3\xa9".to_vec())]),
+            text_row(&["name"], vec![Value::Bytes(b"caf\xe9".to_vec())]),
+        ];
+        let strings = rows_to_strings(rows, false, &CellFormatter::default()).unwrap();
+        assert_eq!(
+            strings,
+            vec![
+                vec![Some("name".to_string())],
+                vec![Some("café".to_string())],
+                vec![Some("caf\u{fffd}".to_string())],
+            ]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    cell::Cell,
+    io::{self, Write},
+};
+
+/// Passes writes through until `limit` bytes have been written, then fails every further write,
+/// so a runaway export stops instead of filling the disk. Without a limit it only counts.
+pub struct LimitedWriter<W> {
+    inner: W,
+    limit: Option<u64>,
+    written: u64,
+    exceeded: bool,
+}
+
+impl<W> LimitedWriter<W> {
+    pub fn new(inner: W, limit: Option<u64>) -> Self {
+        LimitedWriter {
+            inner,
+            limit,
+            written: 0,
+            exceeded: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// The number of bytes passed through so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Whether a write was refused because the limit was reached.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl<W> Write for LimitedWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buf = buf;
+        if let Some(limit) = self.limit {
+            let room = limit.saturating_sub(self.written);
+            if room == 0 && !buf.is_empty() {
+                self.exceeded = true;
+                return Err(io::Error::other(format!(
+                    "output reached the {} byte limit",
+                    limit
+                )));
+            }
+            buf = &buf[..buf.len().min(usize::try_from(room).unwrap_or(usize::MAX))];
+        }
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Passes writes through, adding their length to `count`, which stays readable between writes
+/// while the writer itself is borrowed by a formatter. Flushes stop here: `SPLIT_BYTES` has the
+/// format writers flush after every row so the count is current, and passing each of those on
+/// would make the compressor end a block per row. The caller flushes the inner writer when done.
+pub struct CountingWriter<'a, W> {
+    inner: W,
+    count: &'a Cell<u64>,
+}
+
+impl<'a, W> CountingWriter<'a, W> {
+    pub fn new(inner: W, count: &'a Cell<u64>) -> Self {
+        CountingWriter { inner, count }
+    }
+}
+
+impl<W> Write for CountingWriter<'_, W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count.set(self.count.get() + written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_limit() {
+        let mut writer = LimitedWriter::new(Vec::new(), Some(5));
+        writer.write_all(b"abc").unwrap();
+        let err = writer.write_all(b"defg").unwrap_err();
+        assert_eq!(err.to_string(), "output reached the 5 byte limit");
+        assert!(writer.exceeded());
+        assert_eq!(writer.written(), 5);
+        assert_eq!(writer.get_ref(), b"abcde");
+    }
+
+    #[test]
+    fn writing_exactly_the_limit_is_fine() {
+        let mut writer = LimitedWriter::new(Vec::new(), Some(3));
+        writer.write_all(b"abc").unwrap();
+        writer.write_all(b"").unwrap();
+        assert!(!writer.exceeded());
+    }
+
+    #[test]
+    fn only_counts_without_a_limit() {
+        let mut writer = LimitedWriter::new(Vec::new(), None);
+        writer.write_all(&[0; 10_000]).unwrap();
+        assert_eq!(writer.written(), 10_000);
+        assert!(!writer.exceeded());
+    }
+
+    #[test]
+    fn counts_into_a_shared_cell_and_keeps_flushes() {
+        let count = Cell::new(0);
+        let mut output = io::BufWriter::new(Vec::new());
+        let mut writer = CountingWriter::new(&mut output, &count);
+        writer.write_all(b"abc").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(count.get(), 3);
+        writer.write_all(b"de").unwrap();
+        assert_eq!(count.get(), 5);
+        // The flush went no further.
+        assert!(output.get_ref().is_empty());
+        assert_eq!(output.into_inner().unwrap(), b"abcde");
+    }
+}
@@ -0,0 +1,130 @@
+use std::str::FromStr;
+
+/// Digit grouping and decimal separator conventions for numbers in delimited output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberLocale {
+    /// `1,234.56`
+    En,
+    /// `1.234,56`, also used for `es`, `it`, `nl`, and `pt`.
+    De,
+    /// `1 234,56`, grouped with a no-break space.
+    Fr,
+    /// `1'234.56`
+    Ch,
+}
+
+impl FromStr for NumberLocale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "en" => Ok(NumberLocale::En),
+            "de" | "es" | "it" | "nl" | "pt" => Ok(NumberLocale::De),
+            "fr" => Ok(NumberLocale::Fr),
+            "ch" => Ok(NumberLocale::Ch),
+            other => Err(anyhow::anyhow!(
+                "unsupported number locale: {} (expected en, de, es, it, nl, pt, fr or ch)",
+                other
+            )),
+        }
+    }
+}
+
+impl NumberLocale {
+    fn separators(&self) -> (&'static str, &'static str) {
+        match self {
+            NumberLocale::En => (",", "."),
+            NumberLocale::De => (".", ","),
+            NumberLocale::Fr => ("\u{a0}", ","),
+            NumberLocale::Ch => ("'", "."),
+        }
+    }
+
+    /// Reformats a plain decimal number (`-1234.56`), returning `None` for anything else,
+    /// including exponents and zero-padded values such as IDs and postal codes.
+    pub fn format_number(&self, value: &str) -> Option<String> {
+        let (sign, unsigned) = match value.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", value),
+        };
+        let (integer, fraction) = match unsigned.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (unsigned, None),
+        };
+
+        let is_digits = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+        if !is_digits(integer)
+            || !fraction.is_none_or(is_digits)
+            || (integer.len() > 1 && integer.starts_with('0'))
+        {
+            return None;
+        }
+
+        let (group, decimal) = self.separators();
+        let mut formatted = String::from(sign);
+        for (index, digit) in integer.chars().enumerate() {
+            if index > 0 && (integer.len() - index) % 3 == 0 {
+                formatted.push_str(group);
+            }
+            formatted.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            formatted.push_str(decimal);
+            formatted.push_str(fraction);
+        }
+        Some(formatted)
+    }
+
+    /// Reformats every numeric cell below the header row.
+    pub fn apply(&self, rows: &mut [Vec<Option<String>>]) {
+        for row in rows.iter_mut().skip(1) {
+            for cell in row.iter_mut().flatten() {
+                if let Some(formatted) = self.format_number(cell) {
+                    *cell = formatted;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_per_locale() {
+        let en: NumberLocale = "en".parse().unwrap();
+        let de: NumberLocale = "DE".parse().unwrap();
+        assert_eq!(en.format_number("1234.56").as_deref(), Some("1,234.56"));
+        assert_eq!(de.format_number("1234.56").as_deref(), Some("1.234,56"));
+        assert_eq!(de.format_number("-1234567").as_deref(), Some("-1.234.567"));
+        assert_eq!(
+            NumberLocale::Fr.format_number("1234.5").as_deref(),
+            Some("1\u{a0}234,5")
+        );
+        assert_eq!(
+            NumberLocale::Ch.format_number("999").as_deref(),
+            Some("999")
+        );
+        assert!("xx".parse::<NumberLocale>().is_err());
+    }
+
+    #[test]
+    fn leaves_non_numbers_alone() {
+        for value in ["00123", "1e5", "12.", ".5", "abc", "-", ""] {
+            assert_eq!(NumberLocale::De.format_number(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn skips_the_header_row() {
+        let mut rows = vec![
+            vec![Some("1000".to_string()), None],
+            vec![Some("1000".to_string()), None],
+        ];
+        NumberLocale::En.apply(&mut rows);
+        assert_eq!(rows[0][0].as_deref(), Some("1000"));
+        assert_eq!(rows[1][0].as_deref(), Some("1,000"));
+        assert_eq!(rows[1][1], None);
+    }
+}
@@ -1,69 +1,2259 @@
-use std::{env, fs::File};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, IsTerminal, Write},
+    iter,
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
-use mysql::prelude::Queryable;
-use mysql::Pool;
+use mysql::prelude::{Protocol, Queryable};
+use mysql::{Opts, OptsBuilder, Params, PooledConn, QueryResult};
 
-use gold_digger::{get_extension_from_filename, rows_to_strings};
+use gold_digger::cli::FormatOptions;
+use gold_digger::compress::{Compression, Encoder};
+use gold_digger::connection::{
+    apply_session_settings, connect, fetch_schema, fetch_warnings, insert_rows, is_lock_conflict,
+    lock_retry_delay, redact_url, timeout_kind, EXIT_TIMEOUT,
+};
+use gold_digger::diff::{unified_diff, EXIT_OUTPUT_CHANGED};
+use gold_digger::encoding::{OutputEncoding, TranscodingWriter, UnmappablePolicy};
+use gold_digger::format::{split_literal_rows, TypedHeader};
+use gold_digger::interrupt::{self, EXIT_INTERRUPTED};
+use gold_digger::limit::{CountingWriter, LimitedWriter};
+use gold_digger::locale::NumberLocale;
+use gold_digger::manifest::{write_manifest, OutputFile};
+use gold_digger::params::parse_query_params;
+use gold_digger::progress::Progress;
+use gold_digger::prompt::should_prompt_for_password;
+use gold_digger::query_log::QueryLog;
+use gold_digger::server::{detect_server, explain_analyze};
+use gold_digger::sql::{
+    check_query, insert_statement, is_dml, is_server_export, load_query_files, paged_query,
+    redact_query, statement_keywords,
+};
+#[cfg(feature = "ssh")]
+use gold_digger::ssh::{self, SshConfig};
+use gold_digger::summary::{count_nulls, summarize, summary_rows};
+use gold_digger::tee::TeeWriter;
+use gold_digger::timing::{LatencyStats, PhaseTimings};
+#[cfg(feature = "ssl")]
+use gold_digger::tls::cert_utils;
+use gold_digger::tls::{create_tls_connection, ssl_mode_from_url, SslMode, TlsConfig, TlsPolicy};
+#[cfg(all(feature = "verbose", feature = "ssl"))]
+use gold_digger::tls::{format_negotiated_parameters, negotiated_parameters};
+use gold_digger::transform::{
+    add_row_number_column, apply_header_case, check_column_count, chunk_by_rows, distinct_rows,
+    fill_nulls, parse_sort_keys, partition_by_column, resolve_duplicate_columns, sort_rows,
+    transpose, DuplicateColumnPolicy, HeaderCase,
+};
+use gold_digger::value::{BinaryEncoding, CellFormatter};
+use gold_digger::{
+    check_output_writable, column_types, get_chunk_filename, get_env_flag,
+    get_extension_from_filename, get_output_format, get_partition_filename, get_prefixed_env,
+    parse_extension_map, parse_format_token, prefixed_env_name, refuse_symlink, rows_to_strings,
+};
 
-fn main() -> Result<()> {
-    let output_file = match env::var("OUTPUT_FILE") {
+fn main() {
+    // Errors from the driver can echo DATABASE_URL, so mask its password before printing.
+    let result = run();
+    cleanup();
+    if let Err(err) = result {
+        eprintln!("Error: {}", redact_url(&format!("{:?}", err)));
+        exit(1);
+    }
+}
+
+/// Exits after [`cleanup`], so temporary files and the SSH tunnel never outlive the process. Use
+/// it instead of `std::process::exit`.
+fn exit(code: i32) -> ! {
+    cleanup();
+    process::exit(code)
+}
+
+fn cleanup() {
+    // Remove the PEM copy made for TLS_CA_PEM or a PKCS#7 TLS_CA_FILE, if any.
+    #[cfg(feature = "ssl")]
+    let _ = fs::remove_file(cert_utils::inline_ca_path());
+    #[cfg(feature = "ssh")]
+    ssh::close_tunnel();
+}
+
+fn run() -> Result<()> {
+    if env::args().nth(1).as_deref() == Some("validate") {
+        validate_ca_file(env::args().nth(2));
+    }
+
+    let started = Instant::now();
+    let mut timings = PhaseTimings::default();
+    let mut timing_output = env::var("TIMING_OUTPUT").ok();
+    // Every successful return ends up here, so TIMING_OUTPUT is written whichever way the run
+    // finished.
+    let output_changed = export(&mut timings, &mut timing_output)?;
+    timings.total = started.elapsed();
+    if let Some(timing_output) = timing_output {
+        timings.write_json(File::create(timing_output)?)?;
+    }
+
+    if output_changed {
+        exit(EXIT_OUTPUT_CHANGED);
+    }
+    Ok(())
+}
+
+/// Reads the configuration, runs the query, and writes the output, recording each phase in
+/// `timings`. Returns whether the output differs from `COMPARE_TO`.
+fn export(timings: &mut PhaseTimings, timing_output: &mut Option<String>) -> Result<bool> {
+    let env_prefix = env::var("GOLD_DIGGER_ENV_PREFIX").ok();
+    let env_prefix = env_prefix.as_deref();
+
+    let clipboard = get_env_flag("CLIPBOARD");
+    if clipboard && !cfg!(feature = "clipboard") {
+        #[cfg(feature = "verbose")]
+        eprintln!("CLIPBOARD requires the clipboard feature");
+        exit(-1);
+    }
+    let output_file = match get_prefixed_env(env_prefix, "OUTPUT_FILE") {
+        Ok(_) if clipboard => {
+            #[cfg(feature = "verbose")]
+            eprintln!("CLIPBOARD and OUTPUT_FILE cannot be used together");
+            exit(-1);
+        }
         Ok(val) => val,
+        Err(_) if clipboard => String::new(),
         Err(_) => {
             #[cfg(feature = "verbose")]
             eprintln!("couldn't find OUTPUT_FILE in environment variable");
-            std::process::exit(-1);
+            exit(-1);
         }
     };
 
-    let database_url = match env::var("DATABASE_URL") {
+    let database_url = match get_prefixed_env(env_prefix, "DATABASE_URL") {
         Ok(val) => val,
         Err(_) => {
             #[cfg(feature = "verbose")]
             eprintln!("couldn't find DATABASE_URL in environment variable");
-            std::process::exit(-1);
+            exit(-1);
+        }
+    };
+    let (url_ssl_mode, database_url) = match ssl_mode_from_url(&database_url) {
+        Ok(parsed) => parsed,
+        Err(_err) => {
+            #[cfg(feature = "verbose")]
+            eprintln!("Invalid DATABASE_URL: {:#}", _err);
+            exit(-1);
         }
     };
 
-    let database_query = match env::var("DATABASE_QUERY") {
-        Ok(val) => val,
-        Err(_) => {
+    // Keeps the password out of DATABASE_URL, where process listings and shell history see it.
+    let password = match (env::var_os("PASSWORD_FILE"), env::var("PASSWORD_ENV")) {
+        (Some(_), Ok(_)) => {
+            #[cfg(feature = "verbose")]
+            eprintln!("PASSWORD_FILE and PASSWORD_ENV cannot be used together");
+            exit(-1);
+        }
+        (Some(path), Err(_)) => match fs::read_to_string(&path) {
+            Ok(text) => Some(text.trim_end_matches(['\r', '\n']).to_string()),
+            Err(_err) => {
+                #[cfg(feature = "verbose")]
+                eprintln!("Invalid PASSWORD_FILE: {}", _err);
+                exit(-1);
+            }
+        },
+        (None, Ok(name)) => match env::var(&name) {
+            Ok(password) => Some(password),
+            Err(_) => {
+                #[cfg(feature = "verbose")]
+                eprintln!(
+                    "couldn't find {} (named by PASSWORD_ENV) in environment variable",
+                    name
+                );
+                exit(-1);
+            }
+        },
+        (None, Err(_)) => None,
+    };
+
+    let interactive = get_env_flag("INTERACTIVE");
+    let tls_retry = get_env_flag("TLS_RETRY");
+
+    #[cfg(feature = "ssh")]
+    let ssh_config = match env::var("SSH_HOST") {
+        Ok(host) => match SshConfig::new(
+            &host,
+            env::var("SSH_USER").ok(),
+            env::var_os("SSH_KEY").map(PathBuf::from),
+        ) {
+            Ok(ssh_config) => Some(ssh_config),
+            Err(_err) => {
+                #[cfg(feature = "verbose")]
+                eprintln!("Invalid SSH_HOST: {:#}", _err);
+                exit(-1);
+            }
+        },
+        Err(_) => None,
+    };
+    if env::var_os("SSH_HOST").is_none()
+        && (env::var_os("SSH_USER").is_some() || env::var_os("SSH_KEY").is_some())
+    {
+        #[cfg(feature = "verbose")]
+        eprintln!("SSH_USER and SSH_KEY require SSH_HOST");
+        exit(-1);
+    }
+    #[cfg(not(feature = "ssh"))]
+    if env::var_os("SSH_HOST").is_some() {
+        #[cfg(feature = "verbose")]
+        eprintln!("SSH tunneling requires the ssh feature");
+        exit(-1);
+    }
+
+    // Prefixed variables win over both unprefixed ones; within each pair, the query file does.
+    let query_names: Vec<String> = env_prefix
+        .into_iter()
+        .flat_map(|prefix| {
+            [
+                prefixed_env_name(prefix, "DATABASE_QUERY_FILE"),
+                prefixed_env_name(prefix, "DATABASE_QUERY"),
+            ]
+        })
+        .chain([
+            "DATABASE_QUERY_FILE".to_string(),
+            "DATABASE_QUERY".to_string(),
+        ])
+        .collect();
+    let database_query = query_names
+        .iter()
+        .find_map(|name| env::var_os(name).map(|value| (name, value)));
+    let database_query = match database_query {
+        Some((name, patterns)) if name.ends_with("_FILE") => match load_query_files(&patterns) {
+            Ok(query) => Some(query),
+            Err(_err) => {
+                #[cfg(feature = "verbose")]
+                eprintln!("Invalid {}: {:#}", name, _err);
+                exit(-1);
+            }
+        },
+        Some((_, query)) => query.into_string().ok(),
+        None => None,
+    };
+    let database_query = match database_query {
+        Some(val) => val,
+        None => {
             #[cfg(feature = "verbose")]
             eprintln!("couldn't find DATABASE_QUERY in environment variable");
-            std::process::exit(-1);
+            exit(-1);
+        }
+    };
+
+    let compression_level = parse_env_or_exit::<i32>("COMPRESS_LEVEL");
+
+    let output_encoding =
+        parse_env_or_exit::<OutputEncoding>("OUTPUT_ENCODING").unwrap_or(OutputEncoding::Utf8);
+    let on_unmappable =
+        parse_env_or_exit::<UnmappablePolicy>("ON_UNMAPPABLE").unwrap_or(UnmappablePolicy::Replace);
+
+    let ca_file = match (env::var_os("TLS_CA_FILE"), env::var("TLS_CA_PEM")) {
+        (Some(_), Ok(_)) => {
+            #[cfg(feature = "verbose")]
+            eprintln!("TLS_CA_FILE and TLS_CA_PEM cannot be used together");
+            exit(-1);
+        }
+        (Some(ca_file), Err(_)) => Some(driver_ca_file(PathBuf::from(ca_file))),
+        (None, Ok(pem)) => Some(inline_ca_file(&pem)),
+        (None, Err(_)) => None,
+    };
+    let tls_policy = parse_env_or_exit::<TlsPolicy>("TLS").unwrap_or(TlsPolicy::Auto);
+    let env_ssl_mode = parse_env_or_exit::<SslMode>("SSL_MODE");
+    let resolve_tls_config = |url_ssl_mode: Option<SslMode>| {
+        tls_policy
+            // SSL_MODE takes precedence over an ssl-mode in the database URL.
+            .resolve(env_ssl_mode.or(url_ssl_mode), ca_file.is_some())
+            .and_then(|ssl_mode| TlsConfig::new(ssl_mode, ca_file.clone()))
+    };
+    let tls_config = match resolve_tls_config(url_ssl_mode) {
+        Ok(tls_config) => tls_config,
+        Err(_err) => {
+            #[cfg(feature = "verbose")]
+            eprintln!("{}", _err);
+            exit(-1);
+        }
+    };
+
+    // Check the CA file now so a bad path or bundle fails as a configuration error rather than
+    // surfacing later as a connection failure.
+    #[cfg(feature = "ssl")]
+    if let Some(ca_file) = &tls_config.ca_file {
+        match cert_utils::validate_ca_file(ca_file) {
+            // Shows which bundle is actually in effect when troubleshooting trust errors.
+            #[cfg(feature = "verbose")]
+            Ok(certificates) => {
+                for certificate in &certificates {
+                    eprintln!(
+                        "Loaded CA certificate {} (SHA-256 {})",
+                        cert_utils::format_name(certificate.subject_name()),
+                        cert_utils::fingerprint(certificate)?
+                    );
+                }
+            }
+            #[cfg(not(feature = "verbose"))]
+            Ok(_) => {}
+            Err(_err) => {
+                #[cfg(feature = "verbose")]
+                eprintln!("Invalid TLS_CA_FILE: {:#}", _err);
+                exit(-1);
+            }
+        }
+    }
+
+    // Copies the rows into a table on another server, over the same TLS settings.
+    // TO_DB_URL may carry its own ssl-mode, like DATABASE_URL.
+    let copy_target = match (env::var("TO_DB_URL"), env::var("TO_TABLE")) {
+        (Ok(to_db_url), Ok(to_table)) => {
+            match ssl_mode_from_url(&to_db_url).and_then(|(to_ssl_mode, to_db_url)| {
+                let to_opts = Opts::from_url(&to_db_url)?;
+                Ok((to_opts, to_table, resolve_tls_config(to_ssl_mode)?))
+            }) {
+                Ok(copy_target) => Some(copy_target),
+                Err(_err) => {
+                    #[cfg(feature = "verbose")]
+                    eprintln!("Invalid TO_DB_URL: {}", redact_url(&format!("{:#}", _err)));
+                    exit(-1);
+                }
+            }
+        }
+        (Ok(_), Err(_)) | (Err(_), Ok(_)) => {
+            #[cfg(feature = "verbose")]
+            eprintln!("TO_DB_URL and TO_TABLE must be set together");
+            exit(-1);
         }
+        (Err(_), Err(_)) => None,
+    };
+    let sql_batch_size =
+        parse_env_or_exit::<NonZeroUsize>("SQL_BATCH_SIZE").map_or(1000, NonZeroUsize::get);
+
+    // An SSH tunnel only encrypts the hop to the bastion; from there the password travels on to the
+    // database as it would without one.
+    let exposes_password = sends_password_in_cleartext(
+        password.is_some()
+            || Opts::from_url(&database_url).is_ok_and(|opts| has_url_password(&opts)),
+        tls_config.enabled,
+    ) || sends_password_in_cleartext(
+        copy_target
+            .as_ref()
+            .is_some_and(|(to_opts, _, _)| has_url_password(to_opts)),
+        copy_target
+            .as_ref()
+            .is_some_and(|(_, _, to_tls_config)| to_tls_config.enabled),
+    );
+    if exposes_password {
+        if get_env_flag("NO_INSECURE_CREDENTIALS") {
+            eprintln!("Refusing to send the database password without TLS (NO_INSECURE_CREDENTIALS); set TLS=require or SSL_MODE");
+            exit(-1);
+        }
+        eprintln!("WARNING: the database password will be sent over a connection without TLS. Set TLS=require or SSL_MODE to encrypt it.");
+    }
+
+    let strict_utf8 = get_env_flag("STRICT_UTF8");
+    // Filled in just before writing, so NULL_REPORT, SORT_BY, and SUMMARY still see the NULLs.
+    let null_text = env::var("NULL_TEXT").ok();
+    let cell_formatter = CellFormatter {
+        null_text: None,
+        datetime_format: env::var("DATETIME_FORMAT").ok(),
+        binary_encoding: parse_env_or_exit::<BinaryEncoding>("BINARY_ENCODING").unwrap_or_default(),
+        trim: get_env_flag("TRIM_VALUES"),
+        float_precision: parse_env_or_exit::<usize>("FLOAT_PRECISION"),
+    };
+    let strict_columns = !get_env_flag("NO_STRICT_COLUMNS");
+    let header_case = parse_env_or_exit::<HeaderCase>("HEADER_CASE").unwrap_or_default();
+    let on_duplicate_column =
+        parse_env_or_exit::<DuplicateColumnPolicy>("ON_DUPLICATE_COLUMN").unwrap_or_default();
+    let transpose_rows = get_env_flag("TRANSPOSE");
+    let transpose_force = get_env_flag("TRANSPOSE_FORCE");
+    let typed_header = parse_env_or_exit::<TypedHeader>("TYPED_HEADER");
+    if typed_header.is_some() && (transpose_rows || transpose_force) {
+        #[cfg(feature = "verbose")]
+        eprintln!("TYPED_HEADER and TRANSPOSE cannot be used together");
+        exit(-1);
+    }
+    // Naming the columns implies DISTINCT.
+    let distinct_columns: Option<Vec<String>> = env::var("DISTINCT_COLUMNS").ok().map(|columns| {
+        columns
+            .split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .map(String::from)
+            .collect()
+    });
+    let distinct = distinct_columns.is_some() || get_env_flag("DISTINCT");
+    let sort_keys = match env::var("SORT_BY").map(|text| parse_sort_keys(&text)) {
+        Ok(Ok(keys)) => keys,
+        Ok(Err(_err)) => {
+            #[cfg(feature = "verbose")]
+            eprintln!("Invalid SORT_BY: {:#}", _err);
+            exit(-1);
+        }
+        Err(_) => Vec::new(),
+    };
+    let rowid_column = env::var("ROWID_COLUMN").ok();
+    let rowid_column_force = get_env_flag("ROWID_COLUMN_FORCE");
+    let null_report = get_env_flag("NULL_REPORT");
+    let summary_inline = get_env_flag("SUMMARY_INLINE");
+    let show_summary = summary_inline || get_env_flag("SUMMARY");
+    let number_locale = parse_env_or_exit::<NumberLocale>("NUMBER_LOCALE");
+    let atomic_output = get_env_flag("ATOMIC_OUTPUT");
+    let tee = get_env_flag("TEE");
+    let no_follow_symlinks = get_env_flag("NO_FOLLOW_SYMLINKS");
+    let fsync = get_env_flag("FSYNC");
+    let query_log = env::var_os("QUERY_LOG_FILE").map(QueryLog::new);
+    let max_output_bytes = parse_env_or_exit::<u64>("MAX_OUTPUT_BYTES");
+    // A terminal user can see the export working; the periodic lines are for logs.
+    let progress = parse_env_or_exit::<NonZeroU64>("PROGRESS_INTERVAL")
+        .filter(|_| !io::stderr().is_terminal())
+        .map(|secs| Progress::new(Duration::from_secs(secs.get())));
+    let chunk_rows = parse_env_or_exit::<NonZeroUsize>("CHUNK_ROWS");
+    let deadlock_retries = if get_env_flag("RETRY_ON_DEADLOCK") {
+        parse_env_or_exit::<u32>("DEADLOCK_RETRIES").unwrap_or(3)
+    } else {
+        0
+    };
+    let allow_no_result_set = get_env_flag("ALLOW_NO_RESULT_SET");
+    let allow_dml = get_env_flag("ALLOW_DML") && is_dml(&database_query);
+    let output_if_nonempty = get_env_flag("OUTPUT_IF_NONEMPTY");
+    let exit_zero_on_no_rows = get_env_flag("EXIT_ZERO_ON_NO_ROWS") || get_env_flag("ALLOW_EMPTY");
+    let protocol_compress = get_env_flag("PROTOCOL_COMPRESS");
+    let abort_on_warning = get_env_flag("ABORT_ON_WARNING");
+    let server_export = is_server_export(&database_query);
+    let allow_server_export = get_env_flag("ALLOW_SERVER_EXPORT");
+    let schema_only = get_env_flag("SCHEMA_ONLY");
+    let params = match env::var("QUERY_PARAMS_JSON") {
+        Ok(text) => match parse_query_params(&text) {
+            Ok(params) => Some(params),
+            Err(_err) => {
+                #[cfg(feature = "verbose")]
+                eprintln!("Invalid QUERY_PARAMS_JSON: {:#}", _err);
+                exit(-1);
+            }
+        },
+        Err(_) => None,
+    };
+    // An empty parameter list still sends the query as a prepared statement, which returns
+    // typed values over the binary protocol.
+    let params = params.or(get_env_flag("BINARY_PROTOCOL").then_some(Params::Empty));
+    let result_set = parse_env_or_exit::<NonZeroUsize>("RESULT_SET");
+    let page_size = parse_env_or_exit::<NonZeroUsize>("PAGE_SIZE");
+    let order_by = env::var("ORDER_BY").ok();
+    if page_size.is_some() && order_by.is_none() {
+        #[cfg(feature = "verbose")]
+        eprintln!("PAGE_SIZE requires ORDER_BY, a unique column to page on");
+        exit(-1);
+    }
+    let split_by = env::var("SPLIT_BY").ok();
+    if clipboard && (split_by.is_some() || chunk_rows.is_some()) {
+        #[cfg(feature = "verbose")]
+        eprintln!("CLIPBOARD cannot be combined with SPLIT_BY or CHUNK_ROWS");
+        exit(-1);
+    }
+    if split_by.is_some() && chunk_rows.is_some() {
+        #[cfg(feature = "verbose")]
+        eprintln!("SPLIT_BY and CHUNK_ROWS cannot be used together");
+        exit(-1);
+    }
+    let scalar_lenient = get_env_flag("SCALAR_LENIENT");
+    let scalar = scalar_lenient || get_env_flag("SCALAR");
+    if scalar && (split_by.is_some() || chunk_rows.is_some()) {
+        #[cfg(feature = "verbose")]
+        eprintln!("SCALAR cannot be combined with SPLIT_BY or CHUNK_ROWS");
+        exit(-1);
+    }
+    let split_bytes = parse_env_or_exit::<NonZeroU64>("SPLIT_BYTES");
+    if split_bytes.is_some() && (clipboard || split_by.is_some() || chunk_rows.is_some() || scalar)
+    {
+        #[cfg(feature = "verbose")]
+        eprintln!("SPLIT_BYTES cannot be combined with CLIPBOARD, SPLIT_BY, CHUNK_ROWS, or SCALAR");
+        exit(-1);
+    }
+    let manifest = env::var_os("MANIFEST").map(PathBuf::from);
+    let compare_to = env::var_os("COMPARE_TO").map(PathBuf::from);
+    let compare_update = get_env_flag("COMPARE_UPDATE");
+    if compare_to.is_some()
+        && (clipboard || split_by.is_some() || chunk_rows.is_some() || split_bytes.is_some())
+    {
+        #[cfg(feature = "verbose")]
+        eprintln!(
+            "COMPARE_TO cannot be combined with CLIPBOARD, SPLIT_BY, CHUNK_ROWS, or SPLIT_BYTES"
+        );
+        exit(-1);
+    }
+    let explain_only = get_env_flag("EXPLAIN_ONLY");
+    let explain = explain_only || get_env_flag("EXPLAIN_ANALYZE");
+    let explain_output = env::var("EXPLAIN_OUTPUT").ok();
+    // The analyzed statement really runs, so never let it change data.
+    if explain && statement_keywords(&database_query) != ["SELECT"] {
+        #[cfg(feature = "verbose")]
+        eprintln!("EXPLAIN_ANALYZE only supports a single SELECT statement");
+        exit(-1);
+    }
+    let benchmark = parse_env_or_exit::<NonZeroUsize>("BENCHMARK");
+    let benchmark_keep_last = get_env_flag("BENCHMARK_KEEP_LAST");
+    let benchmark_fresh_connection = get_env_flag("BENCHMARK_FRESH_CONNECTION");
+    if benchmark.is_some() && page_size.is_some() {
+        #[cfg(feature = "verbose")]
+        eprintln!("BENCHMARK cannot be combined with PAGE_SIZE");
+        exit(-1);
+    }
+    let connect_retries = parse_env_or_exit::<u32>("CONNECT_RETRIES").unwrap_or(0);
+    let connect_retry_delay =
+        Duration::from_millis(parse_env_or_exit::<u64>("CONNECT_RETRY_DELAY").unwrap_or(1000));
+    let query_timeout_exit_code =
+        parse_env_or_exit::<i32>("QUERY_TIMEOUT_EXIT_CODE").unwrap_or(EXIT_TIMEOUT);
+    let tcp_keepalive_ms = parse_env_or_exit::<u32>("TCP_KEEPALIVE").map(|secs| {
+        secs.checked_mul(1000).unwrap_or_else(|| {
+            #[cfg(feature = "verbose")]
+            eprintln!("Invalid TCP_KEEPALIVE: {} seconds is too long", secs);
+            exit(-1);
+        })
+    });
+
+    let default_format =
+        env::var("GOLD_DIGGER_DEFAULT_FORMAT")
+            .ok()
+            .map(|token| match parse_format_token(&token) {
+                Ok(default_format) => default_format,
+                Err(_err) => {
+                    #[cfg(feature = "verbose")]
+                    eprintln!("Invalid GOLD_DIGGER_DEFAULT_FORMAT: {}", _err);
+                    exit(-1);
+                }
+            });
+    // The output file's extension takes precedence over the default format, and likewise for
+    // the compression a composite default such as `csv.zst` implies.
+    let extension_map = match env::var("GOLD_DIGGER_EXT_MAP") {
+        Ok(text) => parse_extension_map(&text).unwrap_or_else(|_err| {
+            #[cfg(feature = "verbose")]
+            eprintln!("Invalid GOLD_DIGGER_EXT_MAP: {}", _err);
+            exit(-1);
+        }),
+        Err(_) => HashMap::new(),
+    };
+    let file_format = get_output_format(&output_file, &extension_map);
+    // A template decides the line format itself, whatever the file is called.
+    let output_template = env::var("OUTPUT_TEMPLATE").ok();
+    let format = output_format(
+        output_template.is_some(),
+        file_format,
+        default_format.as_ref().map(|(format, _)| format.as_str()),
+        clipboard,
+    );
+    let compression = parse_env_or_exit::<Compression>("COMPRESS").unwrap_or_else(|| {
+        get_extension_from_filename(&output_file)
+            .and_then(Compression::from_extension)
+            .or_else(|| {
+                default_format
+                    .as_ref()
+                    .filter(|_| file_format.is_none())
+                    .map(|(_, compression)| *compression)
+            })
+            .unwrap_or(Compression::None)
+    });
+    let is_json = cfg!(feature = "json") && matches!(format, Some("json") | Some("ndjson"));
+
+    let mut format_options = FormatOptions::from_env();
+    if get_env_flag("NULL_AWARE_JSON") {
+        format_options.set("null_aware", "true");
+    }
+    if let Ok(field_separator) = env::var("FIELD_SEPARATOR") {
+        format_options.set("field_separator", field_separator);
+    }
+    if let Ok(record_separator) = env::var("RECORD_SEPARATOR") {
+        format_options.set("record_separator", record_separator);
+    }
+    if let Ok(line_ending) = env::var("LINE_ENDING") {
+        format_options.set("line_ending", line_ending);
+    }
+    if let Ok(final_newline) = env::var("FINAL_NEWLINE") {
+        format_options.set("final_newline", final_newline);
+    }
+    if get_env_flag("JSON_PRETTY") {
+        format_options.set("pretty", "true");
+    }
+    if let Ok(indent) = env::var("JSON_PRETTY_INDENT") {
+        format_options.set("indent", indent);
+    }
+    if let Ok(root_key) = env::var("JSON_ROOT_KEY") {
+        format_options.set("root_key", root_key);
+    }
+    if let Ok(key_case) = env::var("JSON_KEY_CASE") {
+        format_options.set("key_case", key_case);
+    }
+    if let Ok(row_index) = env::var("JSON_ROW_INDEX") {
+        format_options.set("row_index", row_index);
+    }
+    if let Ok(bool_columns) = env::var("JSON_BOOL_COLUMNS") {
+        format_options.set("bool_columns", bool_columns);
+    }
+    if let Some(output_template) = output_template {
+        format_options.set("template", output_template);
+    }
+    if get_env_flag("ECHO_QUERY") {
+        format_options.set("echo_query", redact_url(&redact_query(&database_query)));
+    }
+    if let Err(_err) = format_options.validate() {
+        #[cfg(feature = "verbose")]
+        eprintln!("Invalid format option: {}", _err);
+        exit(-1);
+    }
+    #[cfg(feature = "verbose")]
+    for key in format_options.unknown_keys(format_option_keys(format)) {
+        eprintln!("Ignoring unknown format option: {}", key);
+    }
+
+    let delimiter = match format_options.field_separator()? {
+        Some(field_separator) => field_separator,
+        None => char::from(
+            format_options
+                .delimiter()?
+                .unwrap_or(default_delimiter(format)),
+        )
+        .to_string(),
     };
+    let prepend_rows = env::var("PREPEND_ROWS")
+        .map(|text| split_literal_rows(&text, &delimiter))
+        .unwrap_or_default();
+    let append_rows = env::var("APPEND_ROWS")
+        .map(|text| split_literal_rows(&text, &delimiter))
+        .unwrap_or_default();
+    #[cfg(feature = "verbose")]
+    if is_json && !(prepend_rows.is_empty() && append_rows.is_empty()) {
+        eprintln!("PREPEND_ROWS and APPEND_ROWS are ignored for JSON output.");
+    }
+
+    if get_env_flag("DRY_RUN") {
+        if let Err(_err) = check_query(&database_query) {
+            #[cfg(feature = "verbose")]
+            eprintln!("Invalid DATABASE_QUERY: {}", _err);
+            exit(-1);
+        }
+        if format.is_none() {
+            #[cfg(feature = "verbose")]
+            eprintln!("Couldn't find extension");
+            exit(-1);
+        }
+        if no_follow_symlinks && !clipboard {
+            if let Err(_err) = refuse_symlink(&output_file) {
+                #[cfg(feature = "verbose")]
+                eprintln!("{}", _err);
+                exit(-1);
+            }
+        }
+        if !clipboard {
+            if let Err(_err) = check_output_writable(&output_file) {
+                #[cfg(feature = "verbose")]
+                eprintln!("{}", _err);
+                exit(-1);
+            }
+        }
+        #[cfg(feature = "verbose")]
+        eprintln!("Configuration is valid.");
+        // Nothing ran, so there are no timings to report.
+        *timing_output = None;
+        return Ok(false);
+    }
+
+    let clean_on_interrupt = get_env_flag("CLEAN_ON_INTERRUPT");
+    interrupt::install_handler()?;
 
-    let pool = Pool::new(database_url.as_str())?;
-    let mut conn = pool.get_conn()?;
+    let phase = Instant::now();
+    let opts = Opts::from_url(&database_url)?;
+    let exempt_host = env::var("TLS_ACCEPT_INVALID_HOSTNAME_FOR").ok();
+    let tls_config_for = |tls_config: &TlsConfig, opts: &Opts| match &exempt_host {
+        Some(exempt_host) => tls_config
+            .clone()
+            .exempt_hostname(&opts.get_ip_or_hostname(), exempt_host),
+        None => tls_config.clone(),
+    };
+    let tls_config = tls_config_for(&tls_config, &opts);
+    #[cfg(feature = "ssh")]
+    let target = (opts.get_ip_or_hostname().to_string(), opts.get_tcp_port());
+    let mut builder = OptsBuilder::from_opts(opts).ssl_opts(tls_config.to_ssl_opts());
+    #[cfg(feature = "ssh")]
+    if let Some(ssh_config) = &ssh_config {
+        #[cfg(feature = "verbose")]
+        eprintln!("Opening SSH tunnel through {}...", ssh_config.host);
+        let local_port = ssh::open_tunnel(ssh_config, &target.0, target.1, ssh::TUNNEL_TIMEOUT)?;
+        // The server's socket path is on the far side of the tunnel, so never switch to a local
+        // socket that might belong to a different server.
+        builder = builder
+            .ip_or_hostname(Some("127.0.0.1"))
+            .tcp_port(local_port)
+            .prefer_socket(false);
+    }
+    if password.is_some() {
+        builder = builder.pass(password);
+    }
+    builder = apply_session_settings(
+        builder,
+        tcp_keepalive_ms,
+        protocol_compress,
+        env::var("INIT_COMMAND").ok(),
+    );
+    let mut opts: Opts = builder.into();
 
     #[cfg(feature = "verbose")]
-    println!("Connecting to database...");
-    let result: Vec<mysql::Row> = conn.query(database_query)?;
+    eprintln!("Connecting to database...");
+    let tls_ca_file = env::var_os("TLS_CA_FILE").map(PathBuf::from);
+    let (mut pool, mut conn) = match create_tls_connection(
+        opts.clone(),
+        connect_retries,
+        connect_retry_delay,
+        tls_retry,
+        tls_ca_file.as_deref(),
+    ) {
+        Err(err)
+            if err.downcast_ref().is_some_and(|err| {
+                should_prompt_for_password(
+                    err,
+                    interactive,
+                    opts.get_pass().is_some(),
+                    io::stdin().is_terminal(),
+                )
+            }) =>
+        {
+            let prompt = format!(
+                "Database password for {}: ",
+                opts.get_user().unwrap_or_default()
+            );
+            opts = OptsBuilder::from_opts(opts)
+                .pass(Some(rpassword::prompt_password(&prompt)?))
+                .into();
+            connect(opts.clone(), connect_retries, connect_retry_delay)?
+        }
+        result => result?,
+    };
+    // Only EXPLAIN_ANALYZE depends on the server flavor, so a version string that can't be
+    // parsed is reported and the export goes on.
+    let server = (explain || cfg!(feature = "verbose")).then(|| detect_server(&mut conn));
+    #[cfg(feature = "verbose")]
+    match &server {
+        Some(Ok(server)) => eprintln!("Connected to {}", server),
+        Some(Err(err)) => eprintln!("Connected to an unknown server ({:#}).", err),
+        None => {}
+    }
+    #[cfg(all(feature = "verbose", feature = "ssl"))]
+    if tls_config.enabled {
+        match negotiated_parameters(&mut conn)? {
+            Some((version, cipher)) => {
+                eprintln!("{}", format_negotiated_parameters(&version, &cipher))
+            }
+            None => eprintln!("TLS was requested but the server reports a plaintext session."),
+        }
+    }
+    timings.connect = phase.elapsed();
+
+    if explain {
+        let server = server
+            .unwrap_or_else(|| detect_server(&mut conn))
+            .map_err(|err| err.context("EXPLAIN_ANALYZE needs the server version"))?;
+        let plan = explain_analyze(&mut conn, &server, &database_query)?;
+        match &explain_output {
+            Some(explain_output) => fs::write(explain_output, plan)?,
+            None => eprint!("{}", plan),
+        }
+        if explain_only {
+            return Ok(false);
+        }
+    }
+
+    let phase = Instant::now();
+    let mut schema = None;
+    let result: Vec<mysql::Row> = match (benchmark, page_size) {
+        _ if schema_only => {
+            schema = Some(fetch_schema(&mut conn, &database_query)?);
+            Vec::new()
+        }
+        _ if allow_dml => {
+            let (result, no_result_set) = query_or_exit(
+                &mut conn,
+                &database_query,
+                query_timeout_exit_code,
+                deadlock_retries,
+                result_set,
+                params.as_ref(),
+                query_log.as_ref(),
+            )?;
+            if abort_on_warning {
+                abort_if_warnings(&mut conn)?;
+            }
+            // A script that also selects rows, e.g. `UPDATE ...; SELECT ...`, is exported as usual.
+            if let Some(affected_rows) = no_result_set {
+                eprintln!("{} rows affected.", affected_rows);
+                return Ok(false);
+            }
+            result
+        }
+        (Some(iterations), _) => {
+            let mut latencies = Vec::with_capacity(iterations.get());
+            let mut result = Vec::new();
+            let mut no_result_set = None;
+            for iteration in 1..=iterations.get() {
+                if benchmark_fresh_connection && iteration > 1 {
+                    // Close the previous pool and its connection before opening a new one.
+                    drop(conn);
+                    drop(pool);
+                    (pool, conn) = connect(opts.clone(), connect_retries, connect_retry_delay)?;
+                }
+                let started = Instant::now();
+                (result, no_result_set) = query_or_exit(
+                    &mut conn,
+                    &database_query,
+                    query_timeout_exit_code,
+                    deadlock_retries,
+                    result_set,
+                    params.as_ref(),
+                    query_log.as_ref(),
+                )?;
+                latencies.push(started.elapsed());
+                if abort_on_warning {
+                    abort_if_warnings(&mut conn)?;
+                }
+                #[cfg(feature = "verbose")]
+                eprintln!(
+                    "Iteration {}: {} records in {:?}.",
+                    iteration,
+                    result.len(),
+                    latencies[latencies.len() - 1]
+                );
+            }
+
+            if let Some(stats) = LatencyStats::from_durations(&latencies) {
+                eprintln!("{}", stats);
+                if let Some(timing_output) = timing_output.take() {
+                    stats.write_json(File::create(timing_output)?)?;
+                }
+            }
+            if ends_without_result_set(
+                no_result_set,
+                server_export,
+                allow_server_export,
+                allow_no_result_set,
+            ) || !benchmark_keep_last
+            {
+                return Ok(false);
+            }
+            result
+        }
+        (None, None) => {
+            let (result, no_result_set) = query_or_exit(
+                &mut conn,
+                &database_query,
+                query_timeout_exit_code,
+                deadlock_retries,
+                result_set,
+                params.as_ref(),
+                query_log.as_ref(),
+            )?;
+            if abort_on_warning {
+                abort_if_warnings(&mut conn)?;
+            }
+            if ends_without_result_set(
+                no_result_set,
+                server_export,
+                allow_server_export,
+                allow_no_result_set,
+            ) {
+                return Ok(false);
+            }
+            result
+        }
+        (None, Some(page_size)) => {
+            let order_by = order_by.as_deref().unwrap_or_default();
+            let mut result = Vec::new();
+            loop {
+                let page = paged_query(&database_query, order_by, page_size.get(), result.len());
+                let (rows, _) = query_or_exit(
+                    &mut conn,
+                    &page,
+                    query_timeout_exit_code,
+                    deadlock_retries,
+                    result_set,
+                    params.as_ref(),
+                    query_log.as_ref(),
+                )?;
+                if abort_on_warning {
+                    abort_if_warnings(&mut conn)?;
+                }
+                let fetched = rows.len();
+                result.extend(rows);
+                #[cfg(feature = "verbose")]
+                eprintln!("Fetched {} records so far.", result.len());
+                if fetched < page_size.get() {
+                    break;
+                }
+            }
+            result
+        }
+    };
+    timings.query = phase.elapsed();
     #[cfg(feature = "verbose")]
-    println!("Outputting {} records in {}.", result.len(), &output_file);
+    eprintln!(
+        "Outputting {} records in {}.",
+        schema
+            .as_ref()
+            .map_or(result.len(), |schema| schema.len() - 1),
+        if clipboard {
+            "the clipboard"
+        } else {
+            &output_file
+        }
+    );
 
-    if result.is_empty() {
+    let mut output_changed = false;
+    let mut output_files = Vec::new();
+    let no_rows = result.is_empty() && schema.is_none();
+    if no_rows {
         #[cfg(feature = "verbose")]
-        println!("No records found in database.");
-        std::process::exit(1);
+        eprintln!("No records found in database.");
+        if !exit_zero_on_no_rows && !output_if_nonempty {
+            exit(1);
+        }
+    }
+
+    let format = match format {
+        Some(format) => format,
+        None => {
+            #[cfg(feature = "verbose")]
+            eprintln!("Couldn't find extension");
+            exit(-1);
+        }
+    };
+    let mut settings = OutputSettings {
+        format,
+        is_json,
+        compression,
+        compression_level,
+        output_encoding,
+        on_unmappable,
+        format_options,
+        prepend_rows,
+        append_rows,
+        atomic: atomic_output,
+        tee,
+        no_follow_symlinks,
+        max_output_bytes,
+        clean_on_interrupt,
+        typed_header: None,
+        progress,
+        fsync,
+        scalar: None,
+        formatted_bytes: None,
+    };
+
+    if no_rows {
+        // JSON still writes its (empty) array, which needs a header row to start from.
+        let rows = if is_json {
+            vec![Vec::new()]
+        } else {
+            Vec::new()
+        };
+        // OUTPUT_IF_NONEMPTY leaves no file at all, so downstream jobs can't pick up an empty
+        // one; otherwise the empty result replaces any earlier export.
+        let writes_file = !output_if_nonempty
+            && !clipboard
+            && chunk_rows.is_none()
+            && split_by.is_none()
+            && split_bytes.is_none();
+        if let Some(compare_to) = &compare_to {
+            let mut formatted = Vec::new();
+            if writes_file {
+                write_rows(&mut formatted, rows.iter().cloned(), &settings, None)?;
+            }
+            output_changed = compare_output(compare_to, &formatted, compare_update)?;
+        }
+        if writes_file {
+            write_output(&output_file, rows, &settings)?;
+            output_files.push(OutputFile {
+                path: output_file.clone(),
+                rows: 0,
+            });
+        }
+        if let Some(manifest) = &manifest {
+            write_manifest_file(manifest, &output_files, atomic_output)?;
+        }
+        return Ok(output_changed);
     } else {
-        let rows = rows_to_strings(result)?;
-        let output = File::create(&output_file)?;
-
-        match get_extension_from_filename(&output_file) {
-            #[cfg(feature = "csv")]
-            Some("csv") => gold_digger::csv::write(rows, output)?,
-            #[cfg(feature = "json")]
-            Some("json") => gold_digger::json::write(rows, output)?,
-            Some(&_) => gold_digger::tab::write(rows, output)?,
+        let phase = Instant::now();
+        let (mut rows, mut types) = match schema {
+            Some(schema) => (schema, vec![Some("VARCHAR".to_string()); 3]),
             None => {
+                let types: Vec<Option<String>> = result
+                    .first()
+                    .map(column_types)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Some)
+                    .collect();
+                (
+                    rows_to_strings(result, strict_utf8, &cell_formatter)?,
+                    types,
+                )
+            }
+        };
+        // Before the duplicate policy, which then also catches names that only differed in case.
+        apply_header_case(&mut rows, header_case);
+        resolve_duplicates(&mut rows, &mut types, on_duplicate_column)?;
+        if strict_columns {
+            let width = rows.first().map_or(0, Vec::len);
+            check_column_count(&rows[1..], width, "row")?;
+            if !is_json {
+                check_column_count(&settings.prepend_rows, width, "PREPEND_ROWS line")?;
+                check_column_count(&settings.append_rows, width, "APPEND_ROWS line")?;
+            }
+        }
+        if distinct {
+            distinct_rows(&mut rows, distinct_columns.as_deref())?;
+        }
+        if !sort_keys.is_empty() {
+            sort_rows(&mut rows, &sort_keys)?;
+        }
+        if null_report {
+            for null_count in count_nulls(&rows) {
+                eprintln!("{}", null_count);
+            }
+        }
+        if transpose_rows || transpose_force {
+            rows = transpose(rows, transpose_force)?;
+        }
+        if let Some(rowid_column) = &rowid_column {
+            add_row_number_column(&mut rows, rowid_column, rowid_column_force)?;
+            types.insert(0, Some("BIGINT".to_string()));
+            if rowid_column_force {
+                resolve_duplicates(&mut rows, &mut types, on_duplicate_column)?;
+            }
+        }
+        if let Some((to_opts, to_table, to_tls_config)) = &copy_target {
+            let to_opts = OptsBuilder::from_opts(to_opts.clone())
+                .ssl_opts(tls_config_for(to_tls_config, to_opts).to_ssl_opts())
+                .into();
+            #[cfg(feature = "verbose")]
+            eprintln!("Connecting to TO_DB_URL...");
+            let (_to_pool, mut to_conn) = connect(to_opts, connect_retries, connect_retry_delay)?;
+            let statement = insert_statement(to_table, rows.first().map_or(&[], Vec::as_slice));
+            let _inserted = insert_rows(&mut to_conn, &statement, &rows[1..], sql_batch_size)?;
+            #[cfg(feature = "verbose")]
+            eprintln!("Copied {} records into {}.", _inserted, to_table);
+        }
+        let summaries = show_summary.then(|| summarize(&rows));
+        if let Some(null_text) = &null_text {
+            fill_nulls(&mut rows, null_text);
+        }
+        if let Some(summaries) = summaries {
+            if summary_inline
+                && !is_json
+                && format != "template"
+                && chunk_rows.is_none()
+                && split_by.is_none()
+                && split_bytes.is_none()
+            {
+                rows.push(Vec::new());
+                rows.extend(summary_rows(&summaries));
+            } else {
+                for summary in summaries {
+                    eprintln!("{}", summary);
+                }
+            }
+        }
+        // JSON consumers expect canonical numbers, so only delimited output is localized.
+        if let Some(number_locale) = number_locale.filter(|_| !is_json) {
+            number_locale.apply(&mut rows);
+        }
+        if scalar {
+            settings.scalar = Some(scalar_value(&rows, &types, scalar_lenient, is_json)?);
+        }
+        settings.typed_header = typed_header.map(|typed_header| (typed_header, types));
+        timings.convert = phase.elapsed();
+
+        interrupt::begin_writing();
+        if interrupt::is_interrupted() {
+            exit(EXIT_INTERRUPTED);
+        }
+
+        // Formatted before writing, since writing consumes the rows.
+        let formatted = match &compare_to {
+            Some(_) => {
+                let mut buffer = Vec::new();
+                write_rows(&mut buffer, rows.iter().cloned(), &settings, None)?;
+                Some(buffer)
+            }
+            None => None,
+        };
+
+        let phase = Instant::now();
+        match (split_by.as_deref(), chunk_rows, split_bytes) {
+            (Some(column), _, _) => {
+                let mut written = HashSet::new();
+                for (value, partition) in partition_by_column(rows, column)? {
+                    let partition_file = get_partition_filename(&output_file, value.as_deref());
+                    if !written.insert(partition_file.clone()) {
+                        anyhow::bail!("more than one {} value maps to {}", column, partition_file);
+                    }
+                    #[cfg(feature = "verbose")]
+                    eprintln!(
+                        "Writing {} records to {}.",
+                        partition.len() - 1,
+                        &partition_file
+                    );
+                    let rows = partition.len() - 1;
+                    write_output(&partition_file, partition, &settings)?;
+                    output_files.push(OutputFile {
+                        path: partition_file.clone(),
+                        rows,
+                    });
+                    exit_if_interrupted(&partition_file, clean_on_interrupt, atomic_output)?;
+                }
+            }
+            (None, None, None) if clipboard => copy_to_clipboard(rows, &settings)?,
+            (None, None, None) => {
+                let row_count = rows.len() - 1;
+                write_output(&output_file, rows, &settings)?;
+                output_files.push(OutputFile {
+                    path: output_file.clone(),
+                    rows: row_count,
+                });
+                exit_if_interrupted(&output_file, clean_on_interrupt, atomic_output)?;
+            }
+            (None, Some(chunk_rows), _) => {
+                for (index, chunk) in (1..).zip(chunk_by_rows(rows, chunk_rows.get())) {
+                    let chunk_file = get_chunk_filename(&output_file, index)
+                        .unwrap_or_else(|| format!("{}-{:05}", output_file, index));
+                    #[cfg(feature = "verbose")]
+                    eprintln!("Writing {} records to {}.", chunk.len() - 1, &chunk_file);
+                    let rows = chunk.len() - 1;
+                    write_output(&chunk_file, chunk, &settings)?;
+                    output_files.push(OutputFile {
+                        path: chunk_file.clone(),
+                        rows,
+                    });
+                    exit_if_interrupted(&chunk_file, clean_on_interrupt, atomic_output)?;
+                }
+            }
+            (None, None, Some(split_bytes)) => {
+                output_files.extend(split_by_bytes(
+                    &output_file,
+                    rows,
+                    split_bytes.get(),
+                    &mut settings,
+                )?);
+            }
+        }
+        timings.write = phase.elapsed();
+
+        if let (Some(compare_to), Some(formatted)) = (&compare_to, formatted) {
+            output_changed = compare_output(compare_to, &formatted, compare_update)?;
+        }
+    }
+
+    if let Some(manifest) = &manifest {
+        write_manifest_file(manifest, &output_files, atomic_output)?;
+    }
+    Ok(output_changed)
+}
+
+/// Compares the formatted output with the reference file, printing a unified diff if they differ.
+/// A missing reference counts as empty. With `update` set, the reference is replaced by the new
+/// output instead of the run failing. Returns whether the run should fail.
+fn compare_output(reference: &Path, formatted: &[u8], update: bool) -> Result<bool> {
+    let expected = match fs::read(reference) {
+        Ok(expected) => expected,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    if expected == formatted {
+        #[cfg(feature = "verbose")]
+        eprintln!("The output matches {}.", reference.display());
+        return Ok(false);
+    }
+
+    let name = reference.display().to_string();
+    // Byte-identical texts only differ after lossy decoding if the input isn't UTF-8.
+    match unified_diff(
+        &String::from_utf8_lossy(&expected),
+        &String::from_utf8_lossy(formatted),
+        &name,
+        "output",
+    ) {
+        Some(diff) => eprint!("{}", diff),
+        None => eprintln!("The output differs from {} in its non-UTF-8 bytes.", name),
+    }
+    if update {
+        fs::write(reference, formatted)?;
+        #[cfg(feature = "verbose")]
+        eprintln!("Updated {}.", name);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+struct OutputSettings<'a> {
+    format: &'a str,
+    is_json: bool,
+    compression: Compression,
+    compression_level: Option<i32>,
+    output_encoding: OutputEncoding,
+    on_unmappable: UnmappablePolicy,
+    format_options: FormatOptions,
+    prepend_rows: Vec<Vec<Option<String>>>,
+    append_rows: Vec<Vec<Option<String>>>,
+    atomic: bool,
+    tee: bool,
+    no_follow_symlinks: bool,
+    max_output_bytes: Option<u64>,
+    clean_on_interrupt: bool,
+    typed_header: Option<(TypedHeader, Vec<Option<String>>)>,
+    progress: Option<Progress>,
+    fsync: bool,
+    scalar: Option<String>,
+    /// Set while splitting by size, to count the formatted text of the file being written.
+    formatted_bytes: Option<Cell<u64>>,
+}
+
+/// Writes the output file. With `atomic` set, the rows go to a temporary file beside it that is
+/// renamed into place only once complete, so readers never see a partial file.
+fn write_output<I>(path: &str, rows: I, settings: &OutputSettings) -> Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+{
+    if settings.atomic {
+        let temp_path = format!("{}.tmp-{}", path, process::id());
+        let result = write_file(&temp_path, rows, settings);
+        if result.is_err() || interrupt::is_interrupted() {
+            let _ = fs::remove_file(&temp_path);
+            return result;
+        }
+        fs::rename(&temp_path, path)?;
+    } else {
+        write_file(path, rows, settings)?;
+    }
+    if settings.fsync {
+        sync_parent_dir(path)?;
+    }
+    Ok(())
+}
+
+/// Writes the MANIFEST listing `files`. With `atomic` set it goes through a temporary file like
+/// the output does, so a reader never sees a partial manifest.
+fn write_manifest_file(path: &Path, files: &[OutputFile], atomic: bool) -> Result<()> {
+    if !atomic {
+        return write_manifest(files, File::create(path)?);
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".tmp-{}", process::id()));
+    let result = File::create(&temp_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| write_manifest(files, file));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Flushes a file's data to disk. Pipes and terminals, such as `/dev/stdout`, can't be synced and
+/// are skipped.
+fn sync_file(file: &File) -> io::Result<()> {
+    match file.sync_all() {
+        Err(err) if err.kind() == io::ErrorKind::InvalidInput => Ok(()),
+        result => result,
+    }
+}
+
+/// Flushes the directory entry of a newly created or renamed file, so the file itself survives a
+/// crash. Only Unix allows syncing a directory.
+fn sync_parent_dir(path: &str) -> io::Result<()> {
+    #[cfg(unix)]
+    if fs::metadata(path)?.is_file() {
+        let parent = std::path::Path::new(path)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(std::path::Path::new("."));
+        sync_file(&File::open(parent)?)?;
+    }
+    Ok(())
+}
+
+fn write_file<I>(path: &str, rows: I, settings: &OutputSettings) -> Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+{
+    if settings.no_follow_symlinks {
+        refuse_symlink(path)?;
+    }
+    let mut file = LimitedWriter::new(File::create(path)?, settings.max_output_bytes);
+    let result = write_encoded(&mut file, rows, settings);
+    if file.exceeded() {
+        drop(file);
+        if settings.clean_on_interrupt {
+            let _ = fs::remove_file(path);
+        }
+        anyhow::bail!(
+            "{} reached MAX_OUTPUT_BYTES ({} bytes), stopping the export",
+            path,
+            settings.max_output_bytes.unwrap_or_default()
+        );
+    }
+    if result.is_ok() && settings.fsync {
+        sync_file(file.get_ref())?;
+    }
+    result
+}
+
+/// Compresses, transcodes, and optionally mirrors the formatted rows into `output`.
+fn write_encoded<I, W>(output: W, rows: I, settings: &OutputSettings) -> Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
+{
+    let mut encoder = Encoder::new(output, settings.compression, settings.compression_level)?;
+    // Counted after transcoding, so the count matches an uncompressed file, but before compression,
+    // whose output only arrives a block at a time.
+    let mut counter;
+    let output: &mut dyn Write = match &settings.formatted_bytes {
+        Some(formatted_bytes) => {
+            formatted_bytes.set(0);
+            counter = CountingWriter::new(&mut encoder, formatted_bytes);
+            &mut counter
+        }
+        None => &mut encoder,
+    };
+
+    let mut sink: Box<dyn Write + '_> = if settings.output_encoding == OutputEncoding::Utf8 {
+        Box::new(output)
+    } else if settings.is_json {
+        #[cfg(feature = "verbose")]
+        eprintln!("JSON output is always UTF-8, ignoring OUTPUT_ENCODING.");
+        Box::new(output)
+    } else {
+        Box::new(TranscodingWriter::new(
+            output,
+            settings.output_encoding,
+            settings.on_unmappable,
+        ))
+    };
+
+    // Mirror the text as written, before compression, so the terminal shows readable output.
+    if settings.tee {
+        sink = Box::new(TeeWriter::new(sink, io::stdout().lock()));
+    }
+
+    write_rows(&mut sink, rows, settings, settings.progress.as_ref())?;
+    drop(sink);
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Formats the rows, along with any typed header, literal rows, and echoed query, into `sink`,
+/// counting them toward `progress`. The rows are consumed as they are written, so a caller that
+/// still needs them can pass an iterator of clones rather than copying them all up front.
+fn write_rows<I, W>(
+    mut sink: W,
+    rows: I,
+    settings: &OutputSettings,
+    progress: Option<&Progress>,
+) -> Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
+{
+    let format_options = &settings.format_options;
+    if let Some(value) = &settings.scalar {
+        sink.write_all(value.as_bytes())?;
+        if format_options.final_newline()? {
+            sink.write_all(format_options.line_ending()?.as_bytes())?;
+        }
+        return Ok(());
+    }
+
+    // Extra rows and comment lines only make sense in delimited output.
+    let delimited = !settings.is_json && settings.format != "template";
+    let mut rows = rows.into_iter();
+    // The header, with whatever goes before or around it.
+    let mut head: Vec<Vec<Option<String>>> = rows.next().into_iter().collect();
+    let mut tail: &[Vec<Option<String>>] = &[];
+    if delimited {
+        if let Some((typed_header, types)) = &settings.typed_header {
+            typed_header.apply(&mut head, types);
+        }
+        head.splice(0..0, settings.prepend_rows.iter().cloned());
+        tail = &settings.append_rows;
+    }
+
+    if let Some(query) = format_options.get("echo_query").filter(|_| delimited) {
+        let line_ending = format_options.line_ending()?;
+        for line in query.lines() {
+            write!(sink, "# {}{}", line, line_ending)?;
+        }
+    }
+    // Every row after the header counts toward the progress total.
+    let rows = head
+        .into_iter()
+        .chain(rows)
+        .chain(tail.iter().cloned())
+        .enumerate()
+        .map(|(index, row)| {
+            if index > 0 {
+                if let Some(progress) = progress {
+                    progress.tick();
+                }
+            }
+            row
+        });
+    match settings.format {
+        #[cfg(feature = "csv")]
+        "csv" => gold_digger::csv::write_streaming(rows, &mut sink, format_options)?,
+        #[cfg(feature = "json")]
+        "json" => gold_digger::json::write(rows, &mut sink, format_options)?,
+        #[cfg(feature = "json")]
+        "ndjson" => gold_digger::json::write_lines(rows, &mut sink, format_options)?,
+        "template" => gold_digger::template::write_streaming(rows, &mut sink, format_options)?,
+        _ => gold_digger::tab::write_streaming(rows, &mut sink, format_options)?,
+    }
+    Ok(())
+}
+
+/// Renders the single cell of a one-row, one-column result for SCALAR: typed JSON (a number,
+/// string, object, or `null`) for the JSON format, or the text as is otherwise. With `lenient`
+/// set, any other shape gives its first row's first column instead of failing.
+fn scalar_value(
+    rows: &[Vec<Option<String>>],
+    types: &[Option<String>],
+    lenient: bool,
+    is_json: bool,
+) -> Result<String> {
+    let data = rows.get(1..).unwrap_or_default();
+    let columns = rows.first().map_or(0, Vec::len);
+    if !lenient && (data.len() != 1 || columns != 1) {
+        anyhow::bail!(
+            "SCALAR needs a result of one row and one column, got {} rows and {} columns",
+            data.len(),
+            columns
+        );
+    }
+    let cell = data
+        .first()
+        .and_then(|row| row.first())
+        .and_then(Option::as_deref);
+    Ok(if is_json {
+        let sql_type = types.first().and_then(Option::as_deref).unwrap_or_default();
+        gold_digger::json::typed_value(cell, sql_type).to_string()
+    } else {
+        cell.unwrap_or_default().to_string()
+    })
+}
+
+/// Writes the rows to numbered files, each led by the header, counting the bytes as they are
+/// written (after transcoding, before compression) and moving on to the next file once
+/// `max_bytes` is reached. A file ends with the row that reaches the limit, so it can run over by
+/// up to one row, and always holds at least one row. JSON closes each file's document after its
+/// last row, so every file is a complete document.
+fn split_by_bytes(
+    output_file: &str,
+    rows: Vec<Vec<Option<String>>>,
+    max_bytes: u64,
+    settings: &mut OutputSettings,
+) -> Result<Vec<OutputFile>> {
+    // Flushing after every row keeps the count current by the time the next row is asked for.
+    settings.format_options.set("flush_rows", "1");
+    settings.formatted_bytes = Some(Cell::new(0));
+    let settings = &*settings;
+
+    let mut rows = rows.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let mut rows = rows.peekable();
+    let mut output_files = Vec::new();
+    for index in 1.. {
+        let chunk_file = get_chunk_filename(output_file, index)
+            .unwrap_or_else(|| format!("{}-{:05}", output_file, index));
+        let mut written_rows = 0;
+        let chunk = iter::once(header.clone()).chain(iter::from_fn(|| {
+            let written = settings.formatted_bytes.as_ref().map_or(0, Cell::get);
+            if written_rows > 0 && written >= max_bytes {
+                return None;
+            }
+            let row = rows.next()?;
+            written_rows += 1;
+            Some(row)
+        }));
+        write_output(&chunk_file, chunk, settings)?;
+        let written = settings.formatted_bytes.as_ref().map_or(0, Cell::get);
+        if written_rows == 1 && written > max_bytes {
+            eprintln!(
+                "WARNING: {} holds a single row but is {} bytes, over SPLIT_BYTES ({} bytes).",
+                chunk_file, written, max_bytes
+            );
+        }
+        #[cfg(feature = "verbose")]
+        eprintln!("Wrote {} records to {}.", written_rows, &chunk_file);
+        output_files.push(OutputFile {
+            path: chunk_file.clone(),
+            rows: written_rows,
+        });
+        exit_if_interrupted(&chunk_file, settings.clean_on_interrupt, settings.atomic)?;
+        if rows.peek().is_none() {
+            break;
+        }
+    }
+    Ok(output_files)
+}
+
+/// Whether a connection would send its password in the clear: the password goes to the server
+/// in the handshake, which without TLS anyone on the path can capture and attack offline.
+fn sends_password_in_cleartext(has_password: bool, tls_enabled: bool) -> bool {
+    has_password && !tls_enabled
+}
+
+fn has_url_password(opts: &Opts) -> bool {
+    opts.get_pass().is_some_and(|pass| !pass.is_empty())
+}
+
+/// Applies the duplicate column policy, resolving the types alongside the values so they stay
+/// aligned with the columns.
+fn resolve_duplicates(
+    rows: &mut Vec<Vec<Option<String>>>,
+    types: &mut Vec<Option<String>>,
+    policy: DuplicateColumnPolicy,
+) -> Result<()> {
+    rows.insert(1, std::mem::take(types));
+    let result = resolve_duplicate_columns(rows, policy);
+    *types = rows.remove(1);
+    result
+}
+
+/// Formats the rows as for a file, then puts the text on the system clipboard instead.
+fn copy_to_clipboard(rows: Vec<Vec<Option<String>>>, settings: &OutputSettings) -> Result<()> {
+    let mut buffer = Vec::new();
+    write_rows(&mut buffer, rows, settings, settings.progress.as_ref())?;
+    #[cfg(feature = "clipboard")]
+    {
+        gold_digger::clipboard::copy(String::from_utf8(buffer)?)
+    }
+    #[cfg(not(feature = "clipboard"))]
+    {
+        anyhow::bail!("CLIPBOARD requires the clipboard feature")
+    }
+}
+
+/// Runs a query, exiting with the timeout exit code if the server or socket timed out, and
+/// running it again up to `deadlock_retries` times if it lost a lock conflict. When the query
+/// holds several statements, the rows of the `result_set`th result set (counting from 1) are
+/// returned, or of the last one with rows by default. Alongside the rows it returns the affected row count
+/// when no statement produced a result set at all, as opposed to an empty one.
+fn query_or_exit(
+    conn: &mut PooledConn,
+    query: &str,
+    query_timeout_exit_code: i32,
+    deadlock_retries: u32,
+    result_set: Option<NonZeroUsize>,
+    params: Option<&Params>,
+    query_log: Option<&QueryLog>,
+) -> Result<(Vec<mysql::Row>, Option<u64>)> {
+    // Parameters need a prepared statement; everything else goes over the text protocol, which
+    // allows several statements.
+    let run = |conn: &mut PooledConn| match params {
+        Some(params) => select_result_set(conn.exec_iter(query, params.clone())?, result_set),
+        None => select_result_set(conn.query_iter(query)?, result_set),
+    };
+
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let outcome = run(conn);
+        if let Some(query_log) = query_log {
+            match &outcome {
+                Ok((rows, result_sets, affected_rows)) => {
+                    let rows = rows.as_ref().map(Vec::len);
+                    let affected_rows = Some(*affected_rows).filter(|_| *result_sets == 0);
+                    query_log.record(query, rows, affected_rows, None, started.elapsed())?;
+                }
+                Err(err) => {
+                    let error = err.to_string();
+                    query_log.record(query, None, None, Some(&error), started.elapsed())?;
+                }
+            }
+        }
+        match outcome {
+            Ok((Some(rows), _, _)) => return Ok((rows, None)),
+            Ok((None, 0, affected_rows)) => return Ok((Vec::new(), Some(affected_rows))),
+            Ok((None, result_sets, _)) => anyhow::bail!(
+                "RESULT_SET {} was requested, but the query returned {} result set(s)",
+                result_set.map_or(0, NonZeroUsize::get),
+                result_sets
+            ),
+            Err(err) if attempt < deadlock_retries && is_lock_conflict(&err) => {
+                attempt += 1;
                 #[cfg(feature = "verbose")]
-                eprintln!("Couldn't find extension");
-                std::process::exit(-1);
+                eprintln!(
+                    "Query attempt {} of {} failed: {}",
+                    attempt,
+                    deadlock_retries + 1,
+                    err
+                );
+                thread::sleep(lock_retry_delay(attempt));
             }
+            Err(err) => match timeout_kind(&err) {
+                Some(_kind) => {
+                    #[cfg(feature = "verbose")]
+                    eprintln!("{}: {}", _kind, redact_url(&err.to_string()));
+                    exit(query_timeout_exit_code);
+                }
+                None => return Err(err.into()),
+            },
         }
     }
+}
 
+/// Reads every result set, keeping the rows of the `result_set`th one that has columns (or the
+/// last one with rows), and returns them with the number of such result sets and the last
+/// affected row count.
+fn select_result_set<T>(
+    mut result: QueryResult<'_, '_, '_, T>,
+    result_set: Option<NonZeroUsize>,
+) -> mysql::Result<(Option<Vec<mysql::Row>>, usize, u64)>
+where
+    T: Protocol,
+{
+    let mut selected_rows: Option<Vec<mysql::Row>> = None;
+    let mut result_sets = 0;
+    let mut affected_rows = 0;
+    while let Some(mut set) = result.iter() {
+        let has_columns = !set.columns().as_ref().is_empty();
+        affected_rows = set.affected_rows();
+        let rows = set.by_ref().collect::<mysql::Result<Vec<mysql::Row>>>()?;
+        if has_columns {
+            result_sets += 1;
+            let selected = match result_set {
+                Some(result_set) => result_set.get() == result_sets,
+                // An empty result set doesn't replace rows from an earlier statement.
+                None => !rows.is_empty() || selected_rows.as_ref().is_none_or(Vec::is_empty),
+            };
+            if selected {
+                selected_rows = Some(rows);
+            }
+        }
+    }
+    Ok((selected_rows, result_sets, affected_rows))
+}
+
+/// Handles a query that returned no result set, given its affected row count: a server-side
+/// export exits with code 1 unless `ALLOW_SERVER_EXPORT` is set. Returns whether the run is done,
+/// as it is for an allowed export or under `ALLOW_NO_RESULT_SET`; otherwise the empty result
+/// goes on to the no-rows handling.
+fn ends_without_result_set(
+    no_result_set: Option<u64>,
+    server_export: bool,
+    allow_server_export: bool,
+    allow_no_result_set: bool,
+) -> bool {
+    let Some(_affected_rows) = no_result_set else {
+        return false;
+    };
+    if server_export {
+        #[cfg(feature = "verbose")]
+        eprintln!(
+            "The query exported {} rows on the server (INTO OUTFILE/DUMPFILE), not to OUTPUT_FILE.",
+            _affected_rows
+        );
+        if !allow_server_export {
+            exit(1);
+        }
+        return true;
+    }
+    #[cfg(feature = "verbose")]
+    if allow_no_result_set {
+        eprintln!(
+            "The statement returned no result set ({} rows affected).",
+            _affected_rows
+        );
+    }
+    allow_no_result_set
+}
+
+/// Fails the export if the last statement raised any warnings, such as data truncated on
+/// conversion, printing them first.
+fn abort_if_warnings(conn: &mut PooledConn) -> Result<()> {
+    // Notes, such as "table already exists" for IF NOT EXISTS, aren't problems with the data.
+    let warnings: Vec<_> = fetch_warnings(conn)?
+        .into_iter()
+        .filter(|(level, _, _)| !level.eq_ignore_ascii_case("note"))
+        .collect();
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    for (level, code, message) in &warnings {
+        eprintln!("{} {}: {}", level, code, message);
+    }
+    anyhow::bail!(
+        "the query raised {} warning(s) and ABORT_ON_WARNING is set",
+        warnings.len()
+    )
+}
+
+fn exit_if_interrupted(path: &str, clean_on_interrupt: bool, atomic: bool) -> Result<()> {
+    if interrupt::is_interrupted() {
+        // Atomic output is discarded rather than renamed into place when interrupted.
+        if atomic {
+            #[cfg(feature = "verbose")]
+            eprintln!("Interrupted, {} was not written.", path);
+            exit(EXIT_INTERRUPTED);
+        }
+        #[cfg(feature = "verbose")]
+        eprintln!("Interrupted, output in {} is incomplete.", path);
+        if clean_on_interrupt {
+            fs::remove_file(path)?;
+        }
+        exit(EXIT_INTERRUPTED);
+    }
     Ok(())
 }
+
+/// Checks the certificates in `TLS_CA_PEM` and writes them to a temporary file for the driver,
+/// exiting if they don't parse.
+fn inline_ca_file(_pem: &str) -> PathBuf {
+    #[cfg(feature = "ssl")]
+    match cert_utils::validate_ca_pem(_pem)
+        .and_then(|certificates| cert_utils::write_ca_bundle(&certificates))
+    {
+        Ok(path) => path,
+        Err(_err) => {
+            #[cfg(feature = "verbose")]
+            eprintln!("Invalid TLS_CA_PEM: {:#}", _err);
+            exit(-1);
+        }
+    }
+    #[cfg(not(feature = "ssl"))]
+    {
+        #[cfg(feature = "verbose")]
+        eprintln!("TLS support requires the ssl feature");
+        exit(-1);
+    }
+}
+
+/// The path to hand the driver for `TLS_CA_FILE`. It only reads PEM and DER, so the certificates
+/// in a PKCS#7 bundle are written to a temporary PEM file, exiting if they don't parse.
+fn driver_ca_file(path: PathBuf) -> PathBuf {
+    #[cfg(feature = "ssl")]
+    if cert_utils::is_pkcs7_file(&path) {
+        return match cert_utils::validate_ca_file(&path)
+            .and_then(|certificates| cert_utils::write_ca_bundle(&certificates))
+        {
+            Ok(path) => path,
+            Err(_err) => {
+                #[cfg(feature = "verbose")]
+                eprintln!("Invalid TLS_CA_FILE: {:#}", _err);
+                exit(-1);
+            }
+        };
+    }
+    path
+}
+
+/// Checks a CA bundle without connecting: `gold_digger validate [path]`, defaulting to
+/// `TLS_CA_FILE`.
+fn validate_ca_file(path: Option<String>) -> ! {
+    let path = match path.or_else(|| env::var("TLS_CA_FILE").ok()) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("couldn't find TLS_CA_FILE in environment variable");
+            exit(-1);
+        }
+    };
+
+    #[cfg(feature = "ssl")]
+    match cert_utils::validate_ca_file(&path) {
+        Ok(certificates) => {
+            println!(
+                "{} contains {} certificate(s):",
+                path.display(),
+                certificates.len()
+            );
+            let mut table = vec![[
+                "SUBJECT".to_string(),
+                "ISSUER".to_string(),
+                "NOT BEFORE".to_string(),
+                "NOT AFTER".to_string(),
+                "STATUS".to_string(),
+            ]];
+            for certificate in &certificates {
+                let status = match cert_utils::certificate_status(certificate) {
+                    Ok(status) => status.to_string(),
+                    Err(err) => format!("unknown ({})", err),
+                };
+                table.push([
+                    cert_utils::format_name(certificate.subject_name()),
+                    cert_utils::format_name(certificate.issuer_name()),
+                    certificate.not_before().to_string(),
+                    certificate.not_after().to_string(),
+                    status,
+                ]);
+            }
+            let widths: Vec<usize> = (0..5)
+                .map(|column| table.iter().map(|row| row[column].chars().count()).max())
+                .map(Option::unwrap_or_default)
+                .collect();
+            for row in &table {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .collect();
+                println!("  {}", cells.join("  ").trim_end());
+            }
+            exit(0);
+        }
+        Err(err) => {
+            eprintln!("{:#}", err);
+            exit(-1);
+        }
+    }
+
+    #[cfg(not(feature = "ssl"))]
+    {
+        eprintln!("validating {} requires the ssl feature", path.display());
+        exit(-1);
+    }
+}
+
+#[cfg(feature = "verbose")]
+fn format_option_keys(format: Option<&str>) -> &'static [&'static str] {
+    match format {
+        #[cfg(feature = "csv")]
+        Some("csv") => gold_digger::csv::OPTIONS,
+        #[cfg(feature = "json")]
+        Some("json") => gold_digger::json::OPTIONS,
+        #[cfg(feature = "json")]
+        Some("ndjson") => gold_digger::json::NDJSON_OPTIONS,
+        Some("template") => gold_digger::template::OPTIONS,
+        _ => gold_digger::tab::OPTIONS,
+    }
+}
+
+/// Picks the output format: a template decides it outright, then the output file's extension,
+/// then `GOLD_DIGGER_DEFAULT_FORMAT`. Spreadsheets paste tab-separated text into cells, so the
+/// clipboard falls back to TSV.
+fn output_format<'a>(
+    template: bool,
+    file_format: Option<&'a str>,
+    default_format: Option<&'a str>,
+    clipboard: bool,
+) -> Option<&'a str> {
+    template
+        .then_some("template")
+        .or(file_format)
+        .or(default_format)
+        .or(clipboard.then_some("tsv"))
+}
+
+fn default_delimiter(format: Option<&str>) -> u8 {
+    match format {
+        #[cfg(feature = "csv")]
+        Some("csv") => gold_digger::csv::DEFAULT_DELIMITER,
+        _ => gold_digger::tab::DEFAULT_DELIMITER,
+    }
+}
+
+fn parse_env_or_exit<T>(name: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(val) => match val.parse::<T>() {
+            Ok(parsed) => Some(parsed),
+            Err(_err) => {
+                #[cfg(feature = "verbose")]
+                eprintln!("Invalid {}: {}", name, _err);
+                exit(-1);
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(format: &str) -> OutputSettings<'_> {
+        OutputSettings {
+            format,
+            is_json: matches!(format, "json" | "ndjson"),
+            compression: Compression::None,
+            compression_level: None,
+            output_encoding: OutputEncoding::Utf8,
+            on_unmappable: UnmappablePolicy::Replace,
+            format_options: FormatOptions::default(),
+            prepend_rows: Vec::new(),
+            append_rows: Vec::new(),
+            atomic: false,
+            tee: false,
+            no_follow_symlinks: false,
+            max_output_bytes: None,
+            clean_on_interrupt: false,
+            typed_header: None,
+            progress: None,
+            fsync: false,
+            scalar: None,
+            formatted_bytes: None,
+        }
+    }
+
+    fn rows(data: &[&[&str]]) -> Vec<Vec<Option<String>>> {
+        data.iter()
+            .map(|row| row.iter().map(|cell| Some(cell.to_string())).collect())
+            .collect()
+    }
+
+    fn format_rows(rows: Vec<Vec<Option<String>>>, settings: &OutputSettings) -> String {
+        let mut output = Vec::new();
+        write_rows(&mut output, rows, settings, None).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn literal_rows_surround_the_data() {
+        let mut settings = settings("csv");
+        settings.format_options.set("quote", "necessary");
+        settings.prepend_rows = split_literal_rows("REPORT,\"a,b\"", ",");
+        settings.append_rows = split_literal_rows("TOTAL,1", ",");
+        assert_eq!(
+            format_rows(rows(&[&["id", "name"], &["1", "x,y"]]), &settings),
+            "REPORT,\"a,b\"\nid,name\n1,\"x,y\"\nTOTAL,1\n"
+        );
+    }
+
+    #[test]
+    fn literal_rows_are_ignored_for_json() {
+        let mut settings = settings("json");
+        settings.prepend_rows = rows(&[&["REPORT"]]);
+        settings.append_rows = rows(&[&["TOTAL"]]);
+        let output = format_rows(rows(&[&["id"], &["1"]]), &settings);
+        assert!(!output.contains("REPORT") && !output.contains("TOTAL"));
+    }
+
+    #[test]
+    fn extension_takes_precedence_over_the_default_format() {
+        assert_eq!(output_format(false, None, Some("csv"), false), Some("csv"));
+        assert_eq!(
+            output_format(false, Some("json"), Some("csv"), false),
+            Some("json")
+        );
+        assert_eq!(
+            output_format(true, Some("json"), Some("csv"), false),
+            Some("template")
+        );
+        assert_eq!(output_format(false, None, None, true), Some("tsv"));
+        assert_eq!(output_format(false, None, None, false), None);
+    }
+
+    #[test]
+    fn echoes_the_query_per_format() {
+        let query = redact_query("SELECT 1 AS id -- IDENTIFIED BY 'x'\nFROM dual");
+        let mut csv = settings("csv");
+        csv.format_options.set("echo_query", query.clone());
+        csv.format_options.set("quote", "necessary");
+        assert_eq!(
+            format_rows(rows(&[&["id"], &["1"]]), &csv),
+            "# SELECT 1 AS id -- IDENTIFIED BY 'x'\n# FROM dual\nid\n1\n"
+        );
+
+        let mut json = settings("json");
+        json.format_options.set(
+            "echo_query",
+            redact_query("CREATE USER u IDENTIFIED BY 'secret'"),
+        );
+        assert_eq!(
+            format_rows(rows(&[&["id"], &["1"]]), &json),
+            "{\"data\":[{\"id\":\"1\"}],\"meta\":{\"query\":\"CREATE USER u IDENTIFIED BY '***'\"}}\n"
+        );
+    }
+
+    #[test]
+    fn empty_results_write_an_empty_export() {
+        assert_eq!(
+            format_rows(vec![Vec::new()], &settings("json")),
+            "{\"data\":[]}\n"
+        );
+        let mut json = settings("json");
+        json.format_options.set("root_key", "results");
+        assert_eq!(format_rows(vec![Vec::new()], &json), "{\"results\":[]}\n");
+        assert_eq!(format_rows(vec![Vec::new()], &settings("ndjson")), "");
+        assert_eq!(format_rows(Vec::new(), &settings("csv")), "");
+        let mut template = settings("template");
+        template.format_options.set("template", "{id}");
+        assert_eq!(format_rows(Vec::new(), &template), "");
+    }
+
+    #[test]
+    fn split_bytes_starts_a_new_file_once_the_limit_is_reached() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.csv");
+        let output = output.to_str().unwrap();
+        let mut settings = settings("csv");
+        settings.format_options.set("quote", "necessary");
+        let data = rows(&[&["id"], &["1"], &["2"], &["3"], &["4"], &["5"]]);
+
+        let files = split_by_bytes(output, data, 7, &mut settings).unwrap();
+        let written: Vec<_> = files
+            .iter()
+            .map(|file| (file.rows, fs::read_to_string(&file.path).unwrap()))
+            .collect();
+        assert_eq!(
+            written,
+            [
+                (2, "id\n1\n2\n".to_string()),
+                (2, "id\n3\n4\n".to_string()),
+                (1, "id\n5\n".to_string()),
+            ]
+        );
+        assert_eq!(files[0].path, get_chunk_filename(output, 1).unwrap());
+    }
+
+    #[test]
+    fn split_bytes_gives_an_oversized_row_a_file_of_its_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.ndjson");
+        let mut settings = settings("ndjson");
+        let data = rows(&[&["name"], &["a long value"], &["b"]]);
+
+        let files = split_by_bytes(output.to_str().unwrap(), data, 1, &mut settings).unwrap();
+        let contents: Vec<_> = files
+            .iter()
+            .map(|file| fs::read_to_string(&file.path).unwrap())
+            .collect();
+        assert_eq!(
+            contents,
+            ["{\"name\":\"a long value\"}\n", "{\"name\":\"b\"}\n"]
+        );
+    }
+
+    #[test]
+    fn split_bytes_writes_a_complete_json_document_per_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.json");
+        let data = rows(&[&["id"], &["1"], &["2"], &["3"]]);
+
+        let files =
+            split_by_bytes(output.to_str().unwrap(), data, 20, &mut settings("json")).unwrap();
+        let contents: Vec<_> = files
+            .iter()
+            .map(|file| fs::read_to_string(&file.path).unwrap())
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                "{\"data\":[{\"id\":\"1\"},{\"id\":\"2\"}]}\n",
+                "{\"data\":[{\"id\":\"3\"}]}\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_bytes_counts_the_transcoded_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.csv");
+        let mut settings = settings("csv");
+        settings.format_options.set("quote", "necessary");
+        settings.output_encoding = OutputEncoding::Latin1;
+        let data = rows(&[&["name"], &["café"], &["thé"]]);
+
+        // "name\ncafé\n" is 10 bytes in Latin-1 but 11 in UTF-8.
+        let files = split_by_bytes(output.to_str().unwrap(), data, 11, &mut settings).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            fs::read(&files[0].path).unwrap(),
+            b"name\ncaf\xe9\nth\xe9\n"
+        );
+    }
+
+    #[test]
+    fn split_bytes_writes_the_header_when_there_are_no_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.csv");
+        let files = split_by_bytes(
+            output.to_str().unwrap(),
+            rows(&[&["id"]]),
+            100,
+            &mut settings("csv"),
+        )
+        .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].rows, 0);
+        assert_eq!(fs::read_to_string(&files[0].path).unwrap(), "\"id\"\n");
+    }
+
+    #[test]
+    fn atomic_manifest_leaves_no_temporary_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.csv");
+        fs::write(&output, "id\n1\n").unwrap();
+        let files = [OutputFile {
+            path: output.to_str().unwrap().to_string(),
+            rows: 1,
+        }];
+        let manifest = dir.path().join("manifest.json");
+        for atomic in [false, true] {
+            fs::remove_file(&manifest).ok();
+            write_manifest_file(&manifest, &files, atomic).unwrap();
+            let written = fs::read_to_string(&manifest).unwrap();
+            assert!(written.starts_with("{\"files\":[{"), "{}", written);
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        }
+    }
+
+    #[test]
+    fn only_allowed_statements_end_without_a_result_set() {
+        // A result set goes on to be written, whatever the flags.
+        assert!(!ends_without_result_set(None, true, true, true));
+        assert!(ends_without_result_set(Some(3), false, false, true));
+        assert!(!ends_without_result_set(Some(3), false, true, false));
+        assert!(ends_without_result_set(Some(3), true, true, false));
+    }
+
+    #[test]
+    fn warns_only_about_passwords_sent_in_the_clear() {
+        assert!(sends_password_in_cleartext(true, false));
+        assert!(!sends_password_in_cleartext(true, true));
+        assert!(!sends_password_in_cleartext(false, false));
+
+        let url_password = |url: &str| has_url_password(&Opts::from_url(url).unwrap());
+        assert!(url_password("mysql://app:secret@db/app"));
+        assert!(!url_password("mysql://app:@db/app"));
+        assert!(!url_password("mysql://app@db/app"));
+    }
+
+    #[test]
+    fn scalar_writes_the_bare_value() {
+        let count = rows(&[&["COUNT(*)"], &["42"]]);
+        let types = [Some("BIGINT".to_string())];
+        assert_eq!(scalar_value(&count, &types, false, true).unwrap(), "42");
+        assert_eq!(scalar_value(&count, &types, false, false).unwrap(), "42");
+
+        let total = rows(&[&["total"], &["1234.50"]]);
+        let types = [Some("DECIMAL".to_string())];
+        assert_eq!(
+            scalar_value(&total, &types, false, true).unwrap(),
+            "\"1234.50\""
+        );
+        assert_eq!(
+            scalar_value(&total, &types, false, false).unwrap(),
+            "1234.50"
+        );
+
+        let mut null = rows(&[&["value"]]);
+        null.push(vec![None]);
+        assert_eq!(scalar_value(&null, &[None], false, true).unwrap(), "null");
+    }
+
+    #[test]
+    fn scalar_needs_a_single_cell_unless_lenient() {
+        let pairs = rows(&[&["id", "name"], &["1", "a"], &["2", "b"]]);
+        let err = scalar_value(&pairs, &[], false, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SCALAR needs a result of one row and one column, got 2 rows and 2 columns"
+        );
+        assert_eq!(scalar_value(&pairs, &[], true, false).unwrap(), "1");
+    }
+
+    #[test]
+    fn compare_to_fails_only_on_a_difference() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = dir.path().join("expected.csv");
+        fs::write(&reference, "id\n1\n").unwrap();
+        assert!(!compare_output(&reference, b"id\n1\n", false).unwrap());
+        assert!(compare_output(&reference, b"id\n2\n", false).unwrap());
+        assert_eq!(fs::read_to_string(&reference).unwrap(), "id\n1\n");
+
+        // A missing reference is empty, so only empty output matches it.
+        let missing = dir.path().join("missing.csv");
+        assert!(!compare_output(&missing, b"", false).unwrap());
+        assert!(compare_output(&missing, b"id\n", false).unwrap());
+    }
+
+    #[test]
+    fn compare_update_replaces_the_reference() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = dir.path().join("expected.csv");
+        fs::write(&reference, "id\n1\n").unwrap();
+        assert!(!compare_output(&reference, b"id\n2\n", true).unwrap());
+        assert_eq!(fs::read_to_string(&reference).unwrap(), "id\n2\n");
+    }
+
+    #[test]
+    fn formatting_borrowed_rows_matches_the_written_file() {
+        let mut settings = settings("csv");
+        settings.format_options.set("quote", "necessary");
+        settings.prepend_rows = rows(&[&["REPORT"]]);
+        settings.append_rows = rows(&[&["TOTAL", "2"]]);
+        settings.typed_header = Some((TypedHeader::Row, vec![Some("INT".to_string()); 2]));
+        let data = rows(&[&["id", "name"], &["1", "a"], &["2", "b"]]);
+
+        let mut formatted = Vec::new();
+        write_rows(&mut formatted, data.iter().cloned(), &settings, None).unwrap();
+        assert_eq!(
+            String::from_utf8(formatted).unwrap(),
+            format_rows(data, &settings)
+        );
+        assert_eq!(
+            format_rows(rows(&[&["id", "name"], &["1", "a"]]), &settings),
+            "REPORT\nid,name\nINT,INT\n1,a\nTOTAL,2\n"
+        );
+    }
+
+    #[test]
+    fn fsync_syncs_files_and_skips_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.csv");
+        let path = path.to_str().unwrap();
+        let mut settings = settings("csv");
+        settings.fsync = true;
+        settings.format_options.set("quote", "necessary");
+        write_output(path, rows(&[&["id"], &["1"]]), &settings).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "id\n1\n");
+
+        // Devices such as /dev/null can't be synced, and have no directory entry to sync.
+        #[cfg(unix)]
+        write_output("/dev/null", rows(&[&["id"], &["1"]]), &settings).unwrap();
+
+        settings.atomic = true;
+        write_output(path, rows(&[&["id"], &["2"]]), &settings).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "id\n2\n");
+    }
+
+    #[test]
+    fn atomic_output_replaces_the_file_only_when_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.csv");
+        let path = path.to_str().unwrap();
+        fs::write(path, "old\n").unwrap();
+        let mut settings = settings("csv");
+        settings.atomic = true;
+        settings.format_options.set("quote", "necessary");
+
+        settings.max_output_bytes = Some(4);
+        assert!(write_output(path, rows(&[&["id"], &["12345"]]), &settings).is_err());
+        assert_eq!(fs::read_to_string(path).unwrap(), "old\n");
+
+        settings.max_output_bytes = None;
+        write_output(path, rows(&[&["id"], &["12345"]]), &settings).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "id\n12345\n");
+        // No temporary file is left beside the output either way.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use mysql::serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// An output file written by the run, with the number of data rows it holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFile {
+    pub path: String,
+    pub rows: usize,
+}
+
+/// Writes a JSON manifest of the output files to `output`, giving each file's path, row count,
+/// size in bytes, and SHA-256 as lowercase hex. Size and hash are `null` for outputs that aren't
+/// regular files, such as `/dev/stdout`.
+pub fn write_manifest<W>(files: &[OutputFile], mut output: W) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut entries = Vec::with_capacity(files.len());
+    for file in files {
+        let (bytes, sha256) = match fs::metadata(&file.path) {
+            Ok(metadata) if metadata.is_file() => (
+                Value::from(metadata.len()),
+                Value::from(sha256_file(Path::new(&file.path))?),
+            ),
+            _ => (Value::Null, Value::Null),
+        };
+        entries.push(json!({
+            "path": file.path,
+            "rows": file.rows,
+            "bytes": bytes,
+            "sha256": sha256,
+        }));
+    }
+    output.write_all(json!({ "files": entries }).to_string().as_bytes())?;
+    output.write_all(b"\n")?;
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_each_file_with_its_size_and_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let chunk = |index: usize, contents: &str| {
+            let path = dir.path().join(format!("out-{:05}.csv", index));
+            fs::write(&path, contents).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let files = [
+            OutputFile {
+                path: chunk(1, "id\n1\n2\n"),
+                rows: 2,
+            },
+            OutputFile {
+                path: chunk(2, ""),
+                rows: 0,
+            },
+            OutputFile {
+                path: "/dev/null".to_string(),
+                rows: 0,
+            },
+        ];
+
+        let mut output = Vec::new();
+        write_manifest(&files, &mut output).unwrap();
+        let manifest: Value = mysql::serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            manifest,
+            json!({ "files": [
+                {
+                    "path": files[0].path,
+                    "rows": 2,
+                    "bytes": 7,
+                    "sha256": "1d1c91d15ace6e21ea5f551e16e717f326992bdf800f6696628859be29b906bf",
+                },
+                {
+                    "path": files[1].path,
+                    "rows": 0,
+                    "bytes": 0,
+                    "sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                },
+                { "path": "/dev/null", "rows": 0, "bytes": null, "sha256": null },
+            ]})
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::{collections::HashMap, fs};
+
+use anyhow::{bail, Context};
+use mysql::{
+    serde_json::{self, Value as Json},
+    Params, Value,
+};
+
+/// Parses named query parameters from a JSON object such as `{"id": 5, "active": true}`, or from
+/// the file named after a leading `@`. Each key binds the `:key` placeholder in the query.
+pub fn parse_query_params(text: &str) -> anyhow::Result<Params> {
+    let json = match text.strip_prefix('@') {
+        Some(path) => {
+            fs::read_to_string(path).with_context(|| format!("couldn't read {}", path))?
+        }
+        None => text.to_string(),
+    };
+    let object = match serde_json::from_str(&json).context("invalid JSON")? {
+        Json::Object(object) => object,
+        other => bail!(
+            "expected a JSON object of named parameters, got {}",
+            json_type(&other)
+        ),
+    };
+    if object.is_empty() {
+        return Ok(Params::Empty);
+    }
+
+    let mut params = HashMap::with_capacity(object.len());
+    for (name, value) in object {
+        let value = json_to_value(&value).with_context(|| format!("parameter {}", name))?;
+        params.insert(name.into_bytes(), value);
+    }
+    Ok(Params::Named(params))
+}
+
+/// Converts a JSON scalar to the MySQL value it binds as, keeping integers and floats apart.
+/// Booleans bind as `1` and `0`, as MySQL stores them.
+pub fn json_to_value(json: &Json) -> anyhow::Result<Value> {
+    Ok(match json {
+        Json::Null => Value::NULL,
+        Json::Bool(b) => Value::Int(i64::from(*b)),
+        Json::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(i), _) => Value::Int(i),
+            (None, Some(u)) => Value::UInt(u),
+            _ => Value::Double(number.as_f64().unwrap_or_default()),
+        },
+        Json::String(text) => Value::Bytes(text.clone().into_bytes()),
+        other => bail!("{} can't be bound as a parameter", json_type(other)),
+    })
+}
+
+fn json_type(json: &Json) -> &'static str {
+    match json {
+        Json::Null => "null",
+        Json::Bool(_) => "a boolean",
+        Json::Number(_) => "a number",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn named(text: &str) -> HashMap<Vec<u8>, Value> {
+        match parse_query_params(text).unwrap() {
+            Params::Named(params) => params,
+            other => panic!("expected named parameters, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn binds_each_json_type() {
+        let params = named(
+            r#"{"id": 5, "big": 18446744073709551615, "ratio": 0.5, "name": "ada", "active": true, "note": null}"#,
+        );
+        assert_eq!(params[&b"id".to_vec()], Value::Int(5));
+        assert_eq!(params[&b"big".to_vec()], Value::UInt(u64::MAX));
+        assert_eq!(params[&b"ratio".to_vec()], Value::Double(0.5));
+        assert_eq!(params[&b"name".to_vec()], Value::Bytes(b"ada".to_vec()));
+        assert_eq!(params[&b"active".to_vec()], Value::Int(1));
+        assert_eq!(params[&b"note".to_vec()], Value::NULL);
+    }
+
+    #[test]
+    fn reads_parameters_from_a_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, r#"{{"id": 7}}"#).unwrap();
+        let params = named(&format!("@{}", file.path().display()));
+        assert_eq!(params[&b"id".to_vec()], Value::Int(7));
+    }
+
+    #[test]
+    fn rejects_anything_but_an_object_of_scalars() {
+        assert!(matches!(parse_query_params("{}").unwrap(), Params::Empty));
+        for text in [
+            "[1, 2]",
+            "5",
+            "not json",
+            r#"{"ids": [1, 2]}"#,
+            r#"{"a": {"b": 1}}"#,
+        ] {
+            assert!(parse_query_params(text).is_err(), "{}", text);
+        }
+        let err = parse_query_params(r#"{"ids": [1]}"#).unwrap_err();
+        assert!(format!("{:#}", err).contains("parameter ids"));
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+/// Reports how many rows have been written every `interval`, so long exports aren't silent when
+/// stderr is a log rather than a terminal. Counts carry across chunk and partition files.
+pub struct Progress {
+    interval: Duration,
+    rows: Cell<usize>,
+    last_report: Cell<Instant>,
+}
+
+impl Progress {
+    pub fn new(interval: Duration) -> Self {
+        Progress {
+            interval,
+            rows: Cell::new(0),
+            last_report: Cell::new(Instant::now()),
+        }
+    }
+
+    /// The number of rows counted so far.
+    pub fn rows(&self) -> usize {
+        self.rows.get()
+    }
+
+    /// Counts one row, printing the total to stderr once the interval has passed. Returns
+    /// whether it reported.
+    pub fn tick(&self) -> bool {
+        self.rows.set(self.rows.get() + 1);
+        if self.last_report.get().elapsed() < self.interval {
+            return false;
+        }
+        self.last_report.set(Instant::now());
+        #[cfg(feature = "verbose")]
+        eprintln!("Processed {} rows.", self.rows.get());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_once_the_interval_has_passed() {
+        let progress = Progress::new(Duration::from_secs(3600));
+        assert!(!progress.tick());
+        assert!(!progress.tick());
+        assert_eq!(progress.rows(), 2);
+
+        let progress = Progress::new(Duration::ZERO);
+        assert!(progress.tick());
+        assert_eq!(progress.rows(), 1);
+    }
+}
@@ -0,0 +1,54 @@
+use mysql::Error;
+
+/// Access denied for user, the server's answer to a missing or wrong password.
+const ACCESS_DENIED: u16 = 1045;
+
+/// Whether a failed connection should be retried with a password typed at the terminal: only when
+/// prompting was asked for, the server denied access, no password was given, and stdin is a
+/// terminal someone can type into.
+pub fn should_prompt_for_password(
+    err: &Error,
+    interactive: bool,
+    has_password: bool,
+    stdin_is_tty: bool,
+) -> bool {
+    interactive
+        && !has_password
+        && stdin_is_tty
+        && matches!(err, Error::MySqlError(err) if err.code == ACCESS_DENIED)
+}
+
+#[cfg(test)]
+mod tests {
+    use mysql::MySqlError;
+
+    use super::*;
+
+    fn server_error(code: u16) -> Error {
+        Error::MySqlError(MySqlError {
+            state: "28000".to_string(),
+            message: "Access denied".to_string(),
+            code,
+        })
+    }
+
+    #[test]
+    fn prompts_only_when_asked_and_access_was_denied() {
+        let denied = server_error(ACCESS_DENIED);
+        assert!(should_prompt_for_password(&denied, true, false, true));
+        assert!(!should_prompt_for_password(&denied, false, false, true));
+        assert!(!should_prompt_for_password(&denied, true, true, true));
+        assert!(!should_prompt_for_password(&denied, true, false, false));
+
+        // Other server errors and failures to connect at all aren't about the password.
+        let unknown_database = server_error(1049);
+        assert!(!should_prompt_for_password(
+            &unknown_database,
+            true,
+            false,
+            true
+        ));
+        let io = Error::IoError(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+        assert!(!should_prompt_for_password(&io, true, false, true));
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use mysql::serde_json::json;
+
+use crate::{connection::redact_url, sql::redact_query};
+
+/// An append-only audit log of executed queries, one JSON object per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryLog {
+    path: PathBuf,
+}
+
+impl QueryLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        QueryLog { path: path.into() }
+    }
+
+    /// Appends a record of one execution of `query`, with passwords and URL credentials
+    /// redacted. `rows` is the number of rows returned, or `None` for a statement without a
+    /// result set, which records `affected_rows` instead. A failed execution records its `error`
+    /// and neither count.
+    pub fn record(
+        &self,
+        query: &str,
+        rows: Option<usize>,
+        affected_rows: Option<u64>,
+        error: Option<&str>,
+        duration: Duration,
+    ) -> anyhow::Result<()> {
+        let record = json!({
+            "timestamp": format_timestamp(SystemTime::now()),
+            "query": redact_url(&redact_query(query)),
+            "rows": rows,
+            "affected_rows": affected_rows,
+            "error": error.map(redact_url),
+            "duration_ms": duration.as_millis() as u64,
+        });
+        let mut line = record.to_string();
+        line.push('\n');
+        // A single write keeps lines whole when several runs share the log.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("couldn't write to {}", self.path.display()))
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp with milliseconds, e.g.
+/// `2024-05-01T12:30:00.250Z`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Howard Hinnant's civil_from_days algorithm.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use mysql::serde_json::{self, Value};
+
+    use super::*;
+
+    fn records(log: &QueryLog) -> Vec<Value> {
+        fs::read_to_string(&log.path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn appends_successes_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = QueryLog::new(dir.path().join("queries.log"));
+        log.record("SELECT 1", Some(1), None, None, Duration::from_millis(5))
+            .unwrap();
+        log.record(
+            "UPDATE t SET x = 1",
+            None,
+            None,
+            Some("Table 'app.t' doesn't exist"),
+            Duration::from_millis(2),
+        )
+        .unwrap();
+
+        let records = records(&log);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["query"], "SELECT 1");
+        assert_eq!(records[0]["rows"], 1);
+        assert_eq!(records[0]["error"], Value::Null);
+        assert_eq!(records[0]["duration_ms"], 5);
+        assert_eq!(records[1]["rows"], Value::Null);
+        assert_eq!(records[1]["affected_rows"], Value::Null);
+        assert_eq!(records[1]["error"], "Table 'app.t' doesn't exist");
+    }
+
+    #[test]
+    fn redacts_credentials_in_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = QueryLog::new(dir.path().join("queries.log"));
+        log.record(
+            "SELECT 1",
+            None,
+            None,
+            Some("couldn't reach mysql://app:secret@db/app"),
+            Duration::ZERO,
+        )
+        .unwrap();
+        let error = records(&log)[0]["error"].as_str().unwrap().to_string();
+        assert!(!error.contains("secret"), "{}", error);
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_714_566_600_250);
+        assert_eq!(format_timestamp(time), "2024-05-01T12:30:00.250Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+
+use mysql::{prelude::Queryable, Row};
+
+use crate::mysql_value_to_string;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerFlavor {
+    MySql,
+    MariaDb,
+}
+
+impl fmt::Display for ServerFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerFlavor::MySql => write!(f, "MySQL"),
+            ServerFlavor::MariaDb => write!(f, "MariaDB"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub flavor: ServerFlavor,
+    pub version: (u16, u16, u16),
+}
+
+impl ServerInfo {
+    pub fn at_least(&self, major: u16, minor: u16, patch: u16) -> bool {
+        self.version >= (major, minor, patch)
+    }
+}
+
+impl fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (major, minor, patch) = self.version;
+        write!(f, "{} {}.{}.{}", self.flavor, major, minor, patch)
+    }
+}
+
+/// Parses the output of `SELECT VERSION()`, e.g. `8.0.34` or `10.11.2-MariaDB-1:10.11.2+maria~ubu2204`.
+pub fn parse_server_version(version: &str) -> Option<ServerInfo> {
+    let version = version.trim();
+    let flavor = if version.to_ascii_lowercase().contains("mariadb") {
+        ServerFlavor::MariaDb
+    } else {
+        ServerFlavor::MySql
+    };
+
+    // MariaDB may report the replication-compatible "5.5.5-" prefix ahead of its real version.
+    let version = match version.strip_prefix("5.5.5-") {
+        Some(rest) if flavor == ServerFlavor::MariaDb => rest,
+        _ => version,
+    };
+
+    let numeric: &str = version
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .next()
+        .unwrap_or_default();
+    let mut parts = numeric.split('.').map(|part| part.parse::<u16>());
+
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().and_then(Result::ok).unwrap_or(0);
+    let patch = parts.next().and_then(Result::ok).unwrap_or(0);
+
+    Some(ServerInfo {
+        flavor,
+        version: (major, minor, patch),
+    })
+}
+
+pub fn detect_server<C>(conn: &mut C) -> anyhow::Result<ServerInfo>
+where
+    C: Queryable,
+{
+    let version: String = conn
+        .query_first("SELECT VERSION()")?
+        .ok_or_else(|| anyhow::anyhow!("server did not report a version"))?;
+
+    parse_server_version(&version)
+        .ok_or_else(|| anyhow::anyhow!("couldn't parse server version: {}", version))
+}
+
+/// Runs the query under the server's `EXPLAIN ANALYZE` (MySQL 8.0.18+) or `ANALYZE FORMAT=JSON`
+/// (MariaDB 10.1+) and returns the plan as text, with actual row counts and timings. The query
+/// really executes, so only use this with statements that are safe to run.
+pub fn explain_analyze<C>(conn: &mut C, server: &ServerInfo, query: &str) -> anyhow::Result<String>
+where
+    C: Queryable,
+{
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let statement = match server.flavor {
+        ServerFlavor::MySql if server.at_least(8, 0, 18) => format!("EXPLAIN ANALYZE {}", query),
+        ServerFlavor::MariaDb if server.at_least(10, 1, 0) => {
+            format!("ANALYZE FORMAT=JSON {}", query)
+        }
+        _ => anyhow::bail!(
+            "EXPLAIN ANALYZE requires MySQL 8.0.18 or MariaDB 10.1 or later, connected to {}",
+            server
+        ),
+    };
+
+    let rows: Vec<Row> = conn.query(statement)?;
+    let mut plan = String::new();
+    for row in rows {
+        let cells: Vec<String> = row.unwrap().iter().map(mysql_value_to_string).collect();
+        plan.push_str(&cells.join("\t"));
+        plan.push('\n');
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(version: &str) -> (ServerFlavor, (u16, u16, u16)) {
+        let info = parse_server_version(version).unwrap();
+        (info.flavor, info.version)
+    }
+
+    #[test]
+    fn parses_mysql_versions() {
+        assert_eq!(parse("8.0.34"), (ServerFlavor::MySql, (8, 0, 34)));
+        assert_eq!(parse("5.7.44-log"), (ServerFlavor::MySql, (5, 7, 44)));
+    }
+
+    #[test]
+    fn parses_mariadb_versions() {
+        assert_eq!(parse("5.5.68-MariaDB"), (ServerFlavor::MariaDb, (5, 5, 68)));
+        assert_eq!(
+            parse("10.11.2-MariaDB-1:10.11.2+maria~ubu2204"),
+            (ServerFlavor::MariaDb, (10, 11, 2))
+        );
+        // The replication-compatible prefix older clients see.
+        assert_eq!(
+            parse("5.5.5-10.6.12-MariaDB"),
+            (ServerFlavor::MariaDb, (10, 6, 12))
+        );
+    }
+
+    #[test]
+    fn parses_vendor_suffixes() {
+        assert_eq!(parse("8.0.35-27"), (ServerFlavor::MySql, (8, 0, 35)));
+        assert_eq!(
+            parse("8.0.36-0ubuntu0.22.04.1"),
+            (ServerFlavor::MySql, (8, 0, 36))
+        );
+        assert_eq!(parse(" 8.4 "), (ServerFlavor::MySql, (8, 4, 0)));
+    }
+
+    #[test]
+    fn rejects_versions_without_a_number() {
+        assert_eq!(parse_server_version(""), None);
+        assert_eq!(parse_server_version("MariaDB"), None);
+    }
+
+    #[test]
+    fn compares_versions() {
+        let info = parse_server_version("8.0.18").unwrap();
+        assert!(info.at_least(8, 0, 18));
+        assert!(!info.at_least(8, 0, 19));
+        assert_eq!(info.to_string(), "MySQL 8.0.18");
+    }
+}
@@ -0,0 +1,630 @@
+use std::{env, ffi::OsStr, fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+
+/// Performs a lightweight lexical sanity check of a query without contacting a server: the query
+/// must contain something other than whitespace and comments, and its quotes, comments, and
+/// parentheses must be balanced.
+pub fn check_query(query: &str) -> anyhow::Result<()> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut open_parens: Vec<usize> = Vec::new();
+    let mut has_content = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' | '"' | '`' => {
+                let start = i;
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => bail!(
+                            "unterminated {} starting at character {}",
+                            quote_name(c),
+                            start + 1
+                        ),
+                        Some('\\') if c != '`' => i += 2,
+                        Some(&q) if q == c => {
+                            // A doubled quote is an escaped quote, not the end of the literal.
+                            if chars.get(i + 1) == Some(&c) {
+                                i += 2;
+                            } else {
+                                break;
+                            }
+                        }
+                        Some(_) => i += 1,
+                    }
+                }
+                has_content = true;
+            }
+            '-' if chars.get(i + 1) == Some(&'-')
+                && chars.get(i + 2).is_none_or(|c| c.is_whitespace()) =>
+            {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let start = i;
+                i += 2;
+                loop {
+                    match chars.get(i) {
+                        None => bail!("unterminated comment starting at character {}", start + 1),
+                        Some('*') if chars.get(i + 1) == Some(&'/') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(_) => i += 1,
+                    }
+                }
+            }
+            '(' => {
+                open_parens.push(i);
+                has_content = true;
+            }
+            ')' => {
+                if open_parens.pop().is_none() {
+                    bail!("unexpected ')' at character {}", i + 1);
+                }
+            }
+            c if c.is_whitespace() || c == ';' => {}
+            _ => has_content = true,
+        }
+        i += 1;
+    }
+
+    if let Some(position) = open_parens.last() {
+        bail!("unclosed '(' at character {}", position + 1);
+    }
+
+    if !has_content {
+        bail!("query is empty");
+    }
+
+    Ok(())
+}
+
+/// A lexical token of a query, keeping its original text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    /// A keyword or identifier, including a backtick-quoted one.
+    Word(&'a str),
+    /// A single- or double-quoted string literal, quotes included.
+    Literal(&'a str),
+    /// Whitespace and comments.
+    Space(&'a str),
+    Punct(&'a str),
+}
+
+fn tokenize<'a>(query: &'a str) -> Vec<Token<'a>> {
+    let chars: Vec<(usize, char)> = query.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(query.len(), |(offset, _)| *offset);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i].1;
+        let token: fn(&'a str) -> Token<'a> = match c {
+            '\'' | '"' | '`' => {
+                i += 1;
+                while i < chars.len() {
+                    match chars[i].1 {
+                        '\\' if c != '`' => i += 2,
+                        q if q == c && next_char(&chars, i + 1) == Some(c) => i += 2,
+                        q if q == c => break,
+                        _ => i += 1,
+                    }
+                }
+                i += 1;
+                if c == '`' {
+                    Token::Word
+                } else {
+                    Token::Literal
+                }
+            }
+            '-' if next_char(&chars, i + 1) == Some('-')
+                && next_char(&chars, i + 2).is_none_or(char::is_whitespace) =>
+            {
+                while i < chars.len() && chars[i].1 != '\n' {
+                    i += 1;
+                }
+                Token::Space
+            }
+            '#' => {
+                while i < chars.len() && chars[i].1 != '\n' {
+                    i += 1;
+                }
+                Token::Space
+            }
+            '/' if next_char(&chars, i + 1) == Some('*') => {
+                i += 2;
+                while i < chars.len()
+                    && !(chars[i].1 == '*' && next_char(&chars, i + 1) == Some('/'))
+                {
+                    i += 1;
+                }
+                i += 2;
+                Token::Space
+            }
+            c if c.is_whitespace() => {
+                while i < chars.len() && chars[i].1.is_whitespace() {
+                    i += 1;
+                }
+                Token::Space
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                    i += 1;
+                }
+                Token::Word
+            }
+            _ => {
+                i += 1;
+                Token::Punct
+            }
+        };
+        tokens.push(token(&query[offset(start)..offset(i)]));
+    }
+    tokens
+}
+
+fn next_char(chars: &[(usize, char)], i: usize) -> Option<char> {
+    chars.get(i).map(|(_, c)| *c)
+}
+
+fn is_word(token: Option<&Token>, word: &str) -> bool {
+    matches!(token, Some(Token::Word(text)) if text.trim_matches('`').eq_ignore_ascii_case(word))
+}
+
+/// Like [`is_word`], but only for the bare word: a backtick-quoted one is an identifier.
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    match token {
+        Token::Word(word) => word.eq_ignore_ascii_case(keyword),
+        _ => false,
+    }
+}
+
+fn is_punct(token: Option<&Token>, punct: &str) -> bool {
+    matches!(token, Some(Token::Punct(text)) if *text == punct)
+}
+
+/// Masks the string literals that hold passwords, so the query can be recorded without the
+/// credentials it sets or checks: the literal after `IDENTIFIED BY` (or `AS`, also following
+/// `WITH <plugin>`), after `PASSWORD =` or `PASSWORD(`, after `SET PASSWORD FOR <user> =`, and
+/// the `VALUES` literals of an `INSERT` or `REPLACE` in the position of a `password` column.
+/// Other literals are left alone, even next to a `password` column.
+pub fn redact_query(query: &str) -> String {
+    let tokens = tokenize(query);
+    // The indexes of the significant tokens, skipping whitespace and comments.
+    let significant: Vec<usize> = (0..tokens.len())
+        .filter(|&index| !matches!(tokens[index], Token::Space(_)))
+        .collect();
+    let at = |position: usize| significant.get(position).map(|&index| &tokens[index]);
+    let mut secrets = Vec::new();
+
+    let mut position = 0;
+    while position < significant.len() {
+        if is_word(at(position), "identified") {
+            let mut next = position + 1;
+            if is_word(at(next), "with") {
+                next += 2;
+            }
+            if is_word(at(next), "by") || is_word(at(next), "as") {
+                next += 1;
+                // The old `IDENTIFIED BY PASSWORD '<hash>'` form.
+                if is_word(at(next), "password") {
+                    next += 1;
+                }
+                secrets.push(next);
+            }
+        } else if is_word(at(position), "password") {
+            let mut next = position + 1;
+            if is_word(at(next), "for") {
+                while next < significant.len()
+                    && !is_punct(at(next), "=")
+                    && !is_punct(at(next), ";")
+                {
+                    next += 1;
+                }
+            }
+            if is_punct(at(next), "=") || is_punct(at(next), "(") {
+                secrets.push(next + 1);
+            }
+        } else if is_word(at(position), "insert") || is_word(at(position), "replace") {
+            insert_secrets(&at, position + 1, &mut secrets);
+        }
+        position += 1;
+    }
+
+    let secrets: Vec<usize> = secrets
+        .into_iter()
+        .filter_map(|position| significant.get(position).copied())
+        .collect();
+    let mut redacted = String::with_capacity(query.len());
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Literal(text) if secrets.contains(&index) => {
+                let quote = &text[..1];
+                redacted.push_str(quote);
+                redacted.push_str("***");
+                redacted.push_str(quote);
+            }
+            Token::Word(text) | Token::Literal(text) | Token::Space(text) | Token::Punct(text) => {
+                redacted.push_str(text)
+            }
+        }
+    }
+    redacted
+}
+
+/// Finds the `VALUES` literals for `password` columns in an `INSERT [INTO] table (columns)
+/// VALUES (...), ...` whose significant tokens start at `position`, after the `INSERT`.
+fn insert_secrets<'a, F>(at: &F, mut position: usize, secrets: &mut Vec<usize>)
+where
+    F: Fn(usize) -> Option<&'a Token<'a>>,
+{
+    // Modifiers, `INTO`, and the possibly qualified table name.
+    while matches!(at(position), Some(Token::Word(_))) || is_punct(at(position), ".") {
+        position += 1;
+    }
+    if !is_punct(at(position), "(") {
+        return;
+    }
+
+    let mut columns = Vec::new();
+    position += 1;
+    while let Some(token) = at(position) {
+        position += 1;
+        match token {
+            Token::Word(_) => columns.push(is_word(Some(token), "password")),
+            Token::Punct(")") => break,
+            _ => {}
+        }
+    }
+    if !columns.contains(&true)
+        || !(is_word(at(position), "values") || is_word(at(position), "value"))
+    {
+        return;
+    }
+
+    // Each row is a parenthesized list; only a value that is a lone literal is a password.
+    position += 1;
+    while is_punct(at(position), "(") {
+        let mut column = 0;
+        let mut depth = 0;
+        let mut value_start = position + 1;
+        position += 1;
+        while let Some(token) = at(position) {
+            match token {
+                Token::Punct("(") => depth += 1,
+                Token::Punct(")") if depth > 0 => depth -= 1,
+                Token::Punct(",") | Token::Punct(")") if depth == 0 => {
+                    let lone_literal = position == value_start + 1
+                        && matches!(at(value_start), Some(Token::Literal(_)));
+                    if lone_literal && columns.get(column) == Some(&true) {
+                        secrets.push(value_start);
+                    }
+                    column += 1;
+                    value_start = position + 1;
+                    if is_punct(at(position), ")") {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            position += 1;
+        }
+        position += 1;
+        if is_punct(at(position), ",") {
+            position += 1;
+        }
+    }
+}
+
+/// Whether the query exports its rows on the server with `INTO OUTFILE` or `INTO DUMPFILE`, in
+/// which case no rows come back to the client. Keywords inside strings, quoted identifiers, and
+/// comments don't count.
+pub fn is_server_export(query: &str) -> bool {
+    let tokens: Vec<Token> = tokenize(query)
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Space(_)))
+        .collect();
+    tokens.windows(2).any(|pair| {
+        let [into, target] = pair else {
+            return false;
+        };
+        is_keyword(into, "into")
+            && (is_keyword(target, "outfile") || is_keyword(target, "dumpfile"))
+    })
+}
+
+/// The leading keyword of each statement in the query, upper-cased: its first word, or for a
+/// `WITH` statement the first top-level word after the common table expressions, such as
+/// `UPDATE` in `WITH ids AS (SELECT ...) UPDATE ...`. Statements are split at top-level `;`.
+pub fn statement_keywords(query: &str) -> Vec<String> {
+    let mut keywords = Vec::new();
+    let mut first_word: Option<&str> = None;
+    let mut keyword: Option<&str> = None;
+    let mut depth = 0usize;
+    let mut after_close = false;
+
+    for token in tokenize(query) {
+        match token {
+            Token::Space(_) => continue,
+            Token::Punct(";") if depth == 0 => {
+                keywords.extend(keyword.or(first_word).map(str::to_ascii_uppercase));
+                (first_word, keyword) = (None, None);
+            }
+            Token::Punct("(") => depth += 1,
+            Token::Punct(")") => {
+                depth = depth.saturating_sub(1);
+                after_close = depth == 0;
+                continue;
+            }
+            Token::Word(word) if keyword.is_none() => match first_word {
+                None => {
+                    first_word = Some(word);
+                    if !word.eq_ignore_ascii_case("with") {
+                        keyword = Some(word);
+                    }
+                }
+                // A CTE body, or its column list, closes just before the statement proper.
+                Some(_) if depth == 0 && after_close && !word.eq_ignore_ascii_case("as") => {
+                    keyword = Some(word);
+                }
+                Some(_) => {}
+            },
+            _ => {}
+        }
+        after_close = false;
+    }
+    keywords.extend(keyword.or(first_word).map(str::to_ascii_uppercase));
+    keywords
+}
+
+/// Whether any statement in the query modifies data: leads with `INSERT`, `UPDATE`, `DELETE`, or
+/// `REPLACE`, after any common table expressions, as [`statement_keywords`] finds them.
+pub fn is_dml(query: &str) -> bool {
+    statement_keywords(query)
+        .iter()
+        .any(|keyword| matches!(keyword.as_str(), "INSERT" | "UPDATE" | "DELETE" | "REPLACE"))
+}
+
+/// Wraps a query so it returns one page of its rows, ordered by `order_by`. Paging with
+/// `LIMIT`/`OFFSET` only returns every row exactly once if `order_by` is unique and the data
+/// doesn't change between pages.
+pub fn paged_query(query: &str, order_by: &str, page_size: usize, offset: usize) -> String {
+    format!(
+        "SELECT * FROM ({}) AS gold_digger_page ORDER BY `{}` LIMIT {} OFFSET {}",
+        query.trim_end_matches(|c: char| c.is_whitespace() || c == ';'),
+        order_by.replace('`', "``"),
+        page_size,
+        offset
+    )
+}
+
+/// Builds a single-row `INSERT` into `table` for the given columns, with a `?` placeholder per
+/// column. `table` may be qualified with its database, as `db.table`.
+pub fn insert_statement(table: &str, columns: &[Option<String>]) -> String {
+    let table: Vec<String> = table.split('.').map(quote_identifier).collect();
+    let columns: Vec<String> = columns
+        .iter()
+        .map(|column| quote_identifier(column.as_deref().unwrap_or_default()))
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table.join("."),
+        columns.join(", "),
+        vec!["?"; columns.len()].join(", ")
+    )
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// Reads and concatenates the query files named by a list of glob patterns, separated like
+/// `PATH` entries. Each pattern's matches are taken in sorted order, and the files are joined
+/// with `;` on a line of its own so they run as consecutive statements, even when a file ends in
+/// a `--` comment.
+pub fn load_query_files(patterns: &OsStr) -> anyhow::Result<String> {
+    let mut statements = Vec::new();
+    for pattern in env::split_paths(patterns) {
+        let pattern = pattern.to_string_lossy();
+        let mut paths = glob::glob(&pattern)
+            .with_context(|| format!("invalid pattern {}", pattern))?
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        if paths.is_empty() {
+            bail!("{} does not match any files", pattern);
+        }
+        paths.sort();
+
+        for path in paths {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("couldn't read {}", path.display()))?;
+            statements.push(
+                text.trim_end_matches(|c: char| c.is_whitespace() || c == ';')
+                    .to_string(),
+            );
+        }
+    }
+    Ok(statements.join("\n;\n"))
+}
+
+fn quote_name(quote: char) -> &'static str {
+    match quote {
+        '\'' => "single-quoted string",
+        '"' => "double-quoted string",
+        _ => "backtick-quoted identifier",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_balanced_queries() {
+        assert!(check_query("SELECT 1").is_ok());
+        assert!(check_query("SELECT ')' AS x, \"it''s\", `a``b` FROM t WHERE (a = 1)").is_ok());
+        assert!(check_query("SELECT 1 -- trailing ' comment\n").is_ok());
+        assert!(check_query("SELECT 1 /* ( */; # '").is_ok());
+    }
+
+    #[test]
+    fn rejects_unbalanced_queries() {
+        let error = check_query("SELECT 'abc").unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "unterminated single-quoted string starting at character 8"
+        );
+        assert!(check_query("SELECT (1").is_err());
+        assert!(check_query("SELECT 1)").is_err());
+        assert!(check_query("SELECT /* 1").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_queries() {
+        assert!(check_query("").is_err());
+        assert!(check_query(" ; -- nothing\n/* here */").is_err());
+    }
+
+    #[test]
+    fn redacts_credentials() {
+        assert_eq!(
+            redact_query("CREATE USER 'bob'@'%' IDENTIFIED BY 's3cret'"),
+            "CREATE USER 'bob'@'%' IDENTIFIED BY '***'"
+        );
+        assert_eq!(
+            redact_query(
+                "ALTER USER bob IDENTIFIED WITH 'caching_sha2_password' BY \"s'3\" REQUIRE SSL"
+            ),
+            "ALTER USER bob IDENTIFIED WITH 'caching_sha2_password' BY \"***\" REQUIRE SSL"
+        );
+        assert_eq!(
+            redact_query("SET PASSWORD FOR 'bob'@'%' = 'it''s'"),
+            "SET PASSWORD FOR 'bob'@'%' = '***'"
+        );
+        assert_eq!(
+            redact_query("UPDATE users SET password = 'x', name = 'bob'"),
+            "UPDATE users SET password = '***', name = 'bob'"
+        );
+        assert_eq!(
+            redact_query("INSERT INTO users SET name = 'bob', `password` = 'x'"),
+            "INSERT INTO users SET name = 'bob', `password` = '***'"
+        );
+    }
+
+    #[test]
+    fn redacts_only_the_password_column_of_an_insert() {
+        assert_eq!(
+            redact_query(
+                "INSERT INTO users (name, `password`) VALUES ('bob', 'pw1'), ('amy', CONCAT('a', 'b'))"
+            ),
+            "INSERT INTO users (name, `password`) VALUES ('bob', '***'), ('amy', CONCAT('a', 'b'))"
+        );
+    }
+
+    #[test]
+    fn leaves_other_literals_alone() {
+        for query in [
+            "SELECT password, 'x' AS y FROM users WHERE name = 'bob'",
+            "SELECT name FROM users WHERE password IS NULL AND name = 'bob'",
+            "SELECT 'IDENTIFIED BY' AS x, 'pw' -- password = 'x'",
+        ] {
+            assert_eq!(redact_query(query), query);
+        }
+    }
+
+    #[test]
+    fn joins_query_files_in_sorted_order() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2-second.sql"), "SELECT 2 -- last;\n").unwrap();
+        fs::write(dir.path().join("1-first.sql"), "SELECT 1 -- no newline").unwrap();
+        fs::write(dir.path().join("notes.txt"), "not SQL").unwrap();
+        let pattern = dir.path().join("*.sql");
+        assert_eq!(
+            load_query_files(pattern.as_os_str()).unwrap(),
+            "SELECT 1 -- no newline\n;\nSELECT 2 -- last"
+        );
+    }
+
+    #[test]
+    fn rejects_a_pattern_without_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.sql"), "SELECT 1").unwrap();
+        let patterns =
+            env::join_paths([dir.path().join("a.sql"), dir.path().join("*.missing")]).unwrap();
+        let err = load_query_files(&patterns).unwrap_err();
+        assert!(
+            err.to_string().ends_with("does not match any files"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn finds_each_statement_keyword() {
+        assert_eq!(statement_keywords("select 1"), ["SELECT"]);
+        assert_eq!(
+            statement_keywords("-- note; here\n(SELECT 1) UNION (SELECT 2);\nUPDATE t SET a = ';'"),
+            ["SELECT", "UPDATE"]
+        );
+        assert_eq!(
+            statement_keywords(
+                "WITH RECURSIVE ids (id) AS (SELECT 1), more AS (SELECT (2)) DELETE FROM t"
+            ),
+            ["DELETE"]
+        );
+        assert_eq!(
+            statement_keywords("WITH ids AS (SELECT 1) SELECT * FROM ids;"),
+            ["SELECT"]
+        );
+        assert!(statement_keywords(" /* nothing */ ; ").is_empty());
+    }
+
+    #[test]
+    fn detects_data_modifying_statements() {
+        assert!(is_dml("update t set a = 1"));
+        assert!(is_dml("/* nightly */ REPLACE INTO t VALUES (1)"));
+        assert!(is_dml(
+            "WITH old AS (SELECT id FROM t WHERE expired) DELETE FROM t WHERE id IN (SELECT id FROM old)"
+        ));
+        assert!(is_dml(
+            "SET @cutoff = NOW(); INSERT INTO archive SELECT * FROM t"
+        ));
+        assert!(!is_dml("SELECT 'UPDATE' FROM t"));
+        assert!(!is_dml("WITH ids AS (SELECT 1) SELECT * FROM ids"));
+        assert!(!is_dml("-- DELETE FROM t\nSELECT 1"));
+    }
+
+    #[test]
+    fn detects_server_side_exports() {
+        assert!(is_server_export(
+            "SELECT * FROM t INTO OUTFILE '/tmp/t.csv'"
+        ));
+        assert!(is_server_export(
+            "select a into\n  dumpfile '/tmp/a' from t"
+        ));
+        assert!(is_server_export(
+            "SELECT a INTO /* here */ OUTFILE '/tmp/a' FROM t"
+        ));
+        for query in [
+            "SELECT 'INTO OUTFILE' FROM t",
+            "SELECT `into` outfile FROM t",
+            "SELECT a INTO @a FROM t -- INTO OUTFILE",
+            "SELECT a /* into outfile */ FROM t",
+            "SELECT a INTO outfiles FROM t",
+            "SELECT a INTO `outfile` FROM t",
+        ] {
+            assert!(!is_server_export(query), "{}", query);
+        }
+    }
+}
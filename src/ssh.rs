@@ -0,0 +1,347 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use ssh2::{Channel, CheckResult, KnownHostFileKind, Session};
+
+/// How long to wait for the bastion to accept the connection and authenticate.
+pub const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The port SSH listens on unless `SSH_HOST` names another.
+pub const DEFAULT_PORT: u16 = 22;
+
+/// How long the forwarding thread sleeps when neither side had anything to relay.
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+/// The running tunnel, kept where [`close_tunnel`] can reach it from any exit path.
+static TUNNEL: Mutex<Option<Tunnel>> = Mutex::new(None);
+
+struct Tunnel {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// A bastion host to reach the database through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub key: Option<PathBuf>,
+}
+
+impl SshConfig {
+    /// Checks the tunnel settings before anything is started. `host` may include a port, as
+    /// `host:port`, and the key file, when given, must exist.
+    pub fn new(host: &str, user: Option<String>, key: Option<PathBuf>) -> anyhow::Result<Self> {
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => {
+                let port = port
+                    .parse::<u16>()
+                    .with_context(|| format!("invalid SSH port {}", port))?;
+                (host, Some(port))
+            }
+            _ => (host, None),
+        };
+        if host.trim().is_empty() {
+            bail!("missing SSH host");
+        }
+        if user.as_deref().is_some_and(str::is_empty) {
+            bail!("SSH user is empty");
+        }
+        if let Some(key) = &key {
+            if !key.is_file() {
+                bail!("SSH key {} does not exist", key.display());
+            }
+        }
+        Ok(SshConfig {
+            host: host.to_string(),
+            port,
+            user,
+            key,
+        })
+    }
+
+    /// The user to log in as: `SSH_USER`, or else the local user, as `ssh` itself defaults to.
+    fn user(&self) -> anyhow::Result<String> {
+        match self.user.clone() {
+            Some(user) => Ok(user),
+            None => env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .context("SSH_USER is not set and the local user name is unknown"),
+        }
+    }
+}
+
+/// Connects to the bastion, checks its host key against `~/.ssh/known_hosts`, and authenticates
+/// with `SSH_KEY` or the SSH agent. A thread then forwards each connection to a free local port
+/// to `target_host:target_port` as seen from the bastion; the port is returned once it listens.
+/// The tunnel runs until [`close_tunnel`].
+pub fn open_tunnel(
+    config: &SshConfig,
+    target_host: &str,
+    target_port: u16,
+    timeout: Duration,
+) -> anyhow::Result<u16> {
+    let port = config.port.unwrap_or(DEFAULT_PORT);
+    let address = (config.host.as_str(), port)
+        .to_socket_addrs()
+        .with_context(|| format!("couldn't resolve SSH host {}", config.host))?
+        .next()
+        .with_context(|| format!("SSH host {} has no address", config.host))?;
+    let stream = TcpStream::connect_timeout(&address, timeout)
+        .with_context(|| format!("couldn't connect to SSH host {}", config.host))?;
+
+    let mut session = Session::new()?;
+    session.set_tcp_stream(stream);
+    session.set_timeout(u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX));
+    session
+        .handshake()
+        .with_context(|| format!("SSH handshake with {} failed", config.host))?;
+    check_host_key(&session, &config.host, port)?;
+
+    let user = config.user()?;
+    match &config.key {
+        Some(key) => session.userauth_pubkey_file(&user, None, key, None),
+        None => session.userauth_agent(&user),
+    }
+    .with_context(|| format!("SSH authentication as {} on {} failed", user, config.host))?;
+    if !session.authenticated() {
+        bail!("SSH authentication as {} on {} failed", user, config.host);
+    }
+    session.set_timeout(0);
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let local_port = listener.local_addr()?.port();
+    listener.set_nonblocking(true)?;
+    // Every channel shares the session, so one thread polls them all without blocking.
+    session.set_blocking(false);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let target_host = target_host.to_string();
+    let thread = thread::spawn({
+        let stop = Arc::clone(&stop);
+        move || forward(&session, &listener, &target_host, target_port, &stop)
+    });
+    *TUNNEL.lock().unwrap_or_else(PoisonError::into_inner) = Some(Tunnel { stop, thread });
+    Ok(local_port)
+}
+
+/// Stops the tunnel, if one is running.
+pub fn close_tunnel() {
+    if let Some(tunnel) = TUNNEL.lock().unwrap_or_else(PoisonError::into_inner).take() {
+        tunnel.stop.store(true, Ordering::Relaxed);
+        let _ = tunnel.thread.join();
+    }
+}
+
+/// Refuses a bastion whose host key isn't the one recorded in `~/.ssh/known_hosts`, as `ssh`
+/// does in batch mode; an unknown host has to be added there first.
+fn check_host_key(session: &Session, host: &str, port: u16) -> anyhow::Result<()> {
+    let Some((key, _)) = session.host_key() else {
+        bail!("SSH host {} sent no host key", host);
+    };
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .context("couldn't find the home directory for ~/.ssh/known_hosts")?;
+    let path = PathBuf::from(home).join(".ssh").join("known_hosts");
+    let mut known_hosts = session.known_hosts()?;
+    known_hosts
+        .read_file(&path, KnownHostFileKind::OpenSSH)
+        .with_context(|| format!("couldn't read {}", path.display()))?;
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => bail!(
+            "the host key of SSH host {} does not match {}",
+            host,
+            path.display()
+        ),
+        CheckResult::NotFound => bail!("SSH host {} is not in {}", host, path.display()),
+        CheckResult::Failure => bail!("couldn't check the host key of SSH host {}", host),
+    }
+}
+
+/// Accepts local connections and relays each through its own channel until `stop` is set.
+fn forward(
+    session: &Session,
+    listener: &TcpListener,
+    target_host: &str,
+    target_port: u16,
+    stop: &AtomicBool,
+) {
+    let mut connections: Vec<(TcpStream, Channel)> = Vec::new();
+    let mut buffer = vec![0; 32 * 1024];
+    while !stop.load(Ordering::Relaxed) {
+        let mut busy = false;
+        match listener.accept() {
+            Ok((stream, _)) => {
+                busy = true;
+                match open_channel(session, target_host, target_port, stop) {
+                    Ok(channel) if stream.set_nonblocking(true).is_ok() => {
+                        connections.push((stream, channel));
+                    }
+                    // Dropping the stream closes it, which the driver reports as a failed
+                    // connection.
+                    Ok(_) => {}
+                    Err(_err) => {
+                        #[cfg(feature = "verbose")]
+                        eprintln!("Couldn't open an SSH channel to the database: {}", _err);
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+
+        connections.retain_mut(|(stream, channel)| {
+            let relayed = relay(stream, channel, &mut buffer)
+                .and_then(|sent| Ok(sent.zip(relay(channel, stream, &mut buffer)?)));
+            match relayed {
+                Ok(Some((sent, received))) => {
+                    busy |= sent || received;
+                    true
+                }
+                Ok(None) | Err(_) => {
+                    let _ = channel.close();
+                    false
+                }
+            }
+        });
+        if !busy {
+            thread::sleep(IDLE_WAIT);
+        }
+    }
+}
+
+fn open_channel(
+    session: &Session,
+    target_host: &str,
+    target_port: u16,
+    stop: &AtomicBool,
+) -> io::Result<Channel> {
+    loop {
+        match session.channel_direct_tcpip(target_host, target_port, None) {
+            Ok(channel) => return Ok(channel),
+            Err(err) => {
+                let err = io::Error::from(err);
+                if err.kind() != io::ErrorKind::WouldBlock || stop.load(Ordering::Relaxed) {
+                    return Err(err);
+                }
+                thread::sleep(IDLE_WAIT);
+            }
+        }
+    }
+}
+
+/// Moves whatever `from` has ready into `to`, without waiting for more. Returns whether anything
+/// was moved, or `None` once `from` is closed.
+fn relay<R, W>(from: &mut R, to: &mut W, buffer: &mut [u8]) -> io::Result<Option<bool>>
+where
+    R: Read,
+    W: Write,
+{
+    match from.read(buffer) {
+        Ok(0) => Ok(None),
+        Ok(read) => {
+            write_all(to, &buffer[..read])?;
+            Ok(Some(true))
+        }
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(Some(false)),
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => Ok(Some(false)),
+        Err(err) => Err(err),
+    }
+}
+
+/// [`Write::write_all`] for a non-blocking writer, waiting out `WouldBlock`.
+fn write_all<W>(to: &mut W, mut data: &[u8]) -> io::Result<()>
+where
+    W: Write,
+{
+    while !data.is_empty() {
+        match to.write(data) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => data = &data[written..],
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(IDLE_WAIT),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    loop {
+        match to.flush() {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(IDLE_WAIT),
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Both ends of a local TCP connection, the first one non-blocking.
+    fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        server.set_nonblocking(true).unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn parses_the_bastion() {
+        let config = SshConfig::new("bastion.example.com:2222", Some("deploy".into()), None);
+        assert_eq!(
+            config.unwrap(),
+            SshConfig {
+                host: "bastion.example.com".to_string(),
+                port: Some(2222),
+                user: Some("deploy".to_string()),
+                key: None,
+            }
+        );
+        // A bare IPv6 address has no port.
+        assert_eq!(SshConfig::new("::1", None, None).unwrap().port, None);
+
+        for (host, user, key, message) in [
+            ("bastion:ssh", None, None, "invalid SSH port ssh"),
+            (" ", None, None, "missing SSH host"),
+            ("bastion", Some(String::new()), None, "SSH user is empty"),
+            (
+                "bastion",
+                None,
+                Some(PathBuf::from("/nonexistent/id_ed25519")),
+                "SSH key /nonexistent/id_ed25519 does not exist",
+            ),
+        ] {
+            let err = SshConfig::new(host, user, key).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn relays_what_is_ready_until_closed() {
+        let (mut from, mut peer) = socket_pair();
+        let mut to = Vec::new();
+        let mut buffer = [0; 4];
+
+        assert_eq!(relay(&mut from, &mut to, &mut buffer).unwrap(), Some(false));
+        peer.write_all(b"hello").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        while relay(&mut from, &mut to, &mut buffer).unwrap() == Some(true) {}
+        assert_eq!(to, b"hello");
+
+        drop(peer);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(relay(&mut from, &mut to, &mut buffer).unwrap(), None);
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericSummary {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSummary {
+    pub name: String,
+    /// The number of non-NULL values in the column.
+    pub count: usize,
+    /// Present when every non-NULL value in the column parses as a finite number.
+    pub numeric: Option<NumericSummary>,
+}
+
+impl fmt::Display for ColumnSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: count={}", self.name, self.count)?;
+        if let Some(numeric) = &self.numeric {
+            write!(
+                f,
+                " min={} max={} sum={} mean={}",
+                numeric.min, numeric.max, numeric.sum, numeric.mean
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How many of a column's values are SQL NULL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NullCount {
+    pub name: String,
+    pub nulls: usize,
+    pub rows: usize,
+}
+
+impl fmt::Display for NullCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} nulls of {} rows",
+            self.name, self.nulls, self.rows
+        )
+    }
+}
+
+/// Counts the NULL cells in each column of a matrix whose first row is the header. A row too
+/// short to reach a column counts as NULL there.
+pub fn count_nulls(rows: &[Vec<Option<String>>]) -> Vec<NullCount> {
+    let header = match rows.first() {
+        Some(header) => header,
+        None => return Vec::new(),
+    };
+
+    header
+        .iter()
+        .enumerate()
+        .map(|(index, name)| NullCount {
+            name: name.clone().unwrap_or_default(),
+            nulls: rows[1..]
+                .iter()
+                .filter(|row| row.get(index).is_none_or(Option::is_none))
+                .count(),
+            rows: rows.len() - 1,
+        })
+        .collect()
+}
+
+pub const HEADER: [&str; 6] = ["column", "count", "min", "max", "sum", "mean"];
+
+/// Summarizes each column of a matrix whose first row is the header.
+pub fn summarize(rows: &[Vec<Option<String>>]) -> Vec<ColumnSummary> {
+    let header = match rows.first() {
+        Some(header) => header,
+        None => return Vec::new(),
+    };
+
+    header
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let values: Vec<&str> = rows[1..]
+                .iter()
+                .filter_map(|row| row.get(index).and_then(|cell| cell.as_deref()))
+                .collect();
+            let numbers: Option<Vec<f64>> = values
+                .iter()
+                // `parse` also accepts `NaN` and `inf`, which are text as far as a report goes.
+                .map(|value| value.trim().parse::<f64>().ok().filter(|n| n.is_finite()))
+                .collect();
+
+            let numeric = numbers
+                .filter(|numbers| !numbers.is_empty())
+                .map(|numbers| {
+                    let sum: f64 = numbers.iter().sum();
+                    NumericSummary {
+                        min: numbers.iter().copied().fold(f64::INFINITY, f64::min),
+                        max: numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                        sum,
+                        mean: sum / numbers.len() as f64,
+                    }
+                });
+
+            ColumnSummary {
+                name: name.clone().unwrap_or_default(),
+                count: values.len(),
+                numeric,
+            }
+        })
+        .collect()
+}
+
+/// Lays the summaries out as rows beneath a `column,count,min,max,sum,mean` header, for appending
+/// to delimited output.
+pub fn summary_rows(summaries: &[ColumnSummary]) -> Vec<Vec<Option<String>>> {
+    let mut rows = vec![HEADER.iter().map(|name| Some(name.to_string())).collect()];
+    rows.extend(summaries.iter().map(|summary| {
+        let mut row = vec![Some(summary.name.clone()), Some(summary.count.to_string())];
+        match &summary.numeric {
+            Some(numeric) => row.extend(
+                [numeric.min, numeric.max, numeric.sum, numeric.mean]
+                    .iter()
+                    .map(|value| Some(value.to_string())),
+            ),
+            None => row.extend([None, None, None, None]),
+        }
+        row
+    }));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(cells: &[&[Option<&str>]]) -> Vec<Vec<Option<String>>> {
+        cells
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.map(str::to_string)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn counts_nulls_per_column() {
+        let matrix = rows(&[
+            &[Some("id"), Some("email")],
+            &[Some("1"), None],
+            &[Some("2"), Some("b@example.com")],
+            &[Some("3")],
+        ]);
+        let counts: Vec<String> = count_nulls(&matrix)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            counts,
+            vec!["id: 0 nulls of 3 rows", "email: 2 nulls of 3 rows"]
+        );
+        assert!(count_nulls(&[]).is_empty());
+    }
+
+    #[test]
+    fn summarizes_numeric_columns() {
+        let matrix = rows(&[
+            &[Some("id"), Some("amount"), Some("name")],
+            &[Some("1"), Some("2.5"), Some("a")],
+            &[Some("2"), None, Some("b")],
+            &[Some("3"), Some("-0.5"), Some("c")],
+            &[Some("4"), Some("4"), None],
+        ]);
+        let summaries = summarize(&matrix);
+
+        assert_eq!(summaries[0].count, 4);
+        assert_eq!(
+            summaries[0].numeric,
+            Some(NumericSummary {
+                min: 1.0,
+                max: 4.0,
+                sum: 10.0,
+                mean: 2.5
+            })
+        );
+        assert_eq!(summaries[1].count, 3);
+        assert_eq!(
+            summaries[1].numeric,
+            Some(NumericSummary {
+                min: -0.5,
+                max: 4.0,
+                sum: 6.0,
+                mean: 2.0
+            })
+        );
+        assert_eq!(summaries[2].count, 3);
+        assert_eq!(summaries[2].numeric, None);
+    }
+
+    #[test]
+    fn non_finite_values_are_not_numeric() {
+        let matrix = rows(&[
+            &[Some("a"), Some("b"), Some("c")],
+            &[Some("1"), Some("1"), Some("1")],
+            &[Some("NaN"), Some("inf"), Some("-infinity")],
+        ]);
+        for summary in summarize(&matrix) {
+            assert_eq!(summary.numeric, None, "{}", summary.name);
+        }
+    }
+}
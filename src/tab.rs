@@ -1,18 +1,78 @@
 use std::io::Write;
 
-use csv::{QuoteStyle, WriterBuilder};
+use csv::{QuoteStyle, Terminator, WriterBuilder};
 
-pub fn write<W>(rows: Vec<Vec<String>>, output: W) -> anyhow::Result<()>
+use crate::{
+    cli::FormatOptions,
+    delimited::{self, FinalNewline},
+    interrupt::is_interrupted,
+};
+
+pub const OPTIONS: &[&str] = &[
+    "delimiter",
+    "quote",
+    "flush_rows",
+    "field_separator",
+    "record_separator",
+    "line_ending",
+    "final_newline",
+    "echo_query",
+];
+pub const DEFAULT_DELIMITER: u8 = b'\t';
+
+pub fn write<W>(
+    rows: Vec<Vec<Option<String>>>,
+    output: W,
+    options: &FormatOptions,
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    write_rows(rows, output, options, None)
+}
+
+/// Writes rows as they arrive, flushing the output every `flush_rows` rows so a crash or a
+/// reader tailing the file sees the rows written so far.
+pub fn write_streaming<I, W>(rows: I, output: W, options: &FormatOptions) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
+{
+    if delimited::is_custom(options) {
+        let delimiter = options.delimiter()?.unwrap_or(DEFAULT_DELIMITER);
+        return delimited::write_streaming(rows, output, options, delimiter);
+    }
+    write_rows(rows, output, options, Some(options.flush_rows()?))
+}
+
+fn write_rows<I, W>(
+    rows: I,
+    output: W,
+    options: &FormatOptions,
+    flush_rows: Option<usize>,
+) -> anyhow::Result<()>
 where
+    I: IntoIterator<Item = Vec<Option<String>>>,
     W: Write,
 {
     let mut wtr = WriterBuilder::new()
-        .delimiter(b'\t')
-        .quote_style(QuoteStyle::Necessary)
-        .from_writer(output);
+        .delimiter(options.delimiter()?.unwrap_or(DEFAULT_DELIMITER))
+        .quote_style(options.quote_style()?.unwrap_or(QuoteStyle::Necessary))
+        .terminator(match options.line_ending()? {
+            "\r\n" => Terminator::CRLF,
+            _ => Terminator::Any(b'\n'),
+        })
+        .flexible(true)
+        .from_writer(FinalNewline::new(output, options.final_newline()?));
 
-    for row in rows.iter() {
-        wtr.write_record(row)?;
+    for (index, row) in rows.into_iter().enumerate() {
+        if is_interrupted() {
+            break;
+        }
+        wtr.write_record(row.iter().map(|cell| cell.as_deref().unwrap_or_default()))?;
+        if flush_rows.is_some_and(|flush_rows| (index + 1) % flush_rows == 0) {
+            wtr.flush()?;
+        }
     }
 
     Ok(())
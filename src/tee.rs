@@ -0,0 +1,46 @@
+use std::io::{self, Write};
+
+/// Copies everything written to it into two writers, e.g. the output file and stdout.
+pub struct TeeWriter<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> TeeWriter<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        TeeWriter { first, second }
+    }
+}
+
+impl<A, B> Write for TeeWriter<A, B>
+where
+    A: Write,
+    B: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.first.write_all(buf)?;
+        self.second.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.first.flush()?;
+        self.second.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_writers_receive_identical_bytes() {
+        let (mut file, mut stdout) = (Vec::new(), Vec::new());
+        let mut tee = TeeWriter::new(&mut file, &mut stdout);
+        tee.write_all(b"id,name\n").unwrap();
+        writeln!(tee, "{},caf\u{e9}", 1).unwrap();
+        tee.flush().unwrap();
+        assert_eq!(file, "id,name\n1,caf\u{e9}\n".as_bytes());
+        assert_eq!(file, stdout);
+    }
+}
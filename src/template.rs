@@ -0,0 +1,207 @@
+use std::{io::Write, str::FromStr};
+
+use anyhow::bail;
+
+use crate::{cli::FormatOptions, interrupt::is_interrupted};
+
+pub const OPTIONS: &[&str] = &["template", "flush_rows", "line_ending", "final_newline"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Column(String),
+}
+
+/// A line format such as `id={id};name={name}`, where each `{column}` placeholder is replaced by
+/// that column's value. `{{` and `}}` stand for literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => {
+                                bail!("unclosed placeholder {{{} in template", name)
+                            }
+                            Some(c) => name.push(c),
+                        }
+                    }
+                    if name.is_empty() {
+                        bail!("empty placeholder {{}} in template");
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Column(name));
+                }
+                '}' => bail!("unmatched }} in template (write }}}} for a literal brace)"),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+}
+
+impl Template {
+    /// Expands the template for one row, given each placeholder's column index from
+    /// [`Template::resolve`]. NULL values are written as empty strings.
+    fn render(&self, indices: &[Option<usize>], row: &[Option<String>], line: &mut String) {
+        for (part, index) in self.parts.iter().zip(indices) {
+            match (part, index) {
+                (Part::Text(text), _) => line.push_str(text),
+                (Part::Column(_), Some(index)) => line.push_str(
+                    row.get(*index)
+                        .and_then(Option::as_deref)
+                        .unwrap_or_default(),
+                ),
+                (Part::Column(_), None) => {}
+            }
+        }
+    }
+
+    /// Looks up each placeholder's column in the header, failing on names it doesn't contain.
+    fn resolve(&self, header: &[Option<String>]) -> anyhow::Result<Vec<Option<usize>>> {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(_) => Ok(None),
+                Part::Column(name) => {
+                    match header.iter().position(|cell| cell.as_deref() == Some(name)) {
+                        Some(index) => Ok(Some(index)),
+                        None => bail!("unknown column {} in template", name),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Writes one line per data row by expanding the `template` format option against it. The header
+/// row only names the columns and isn't written.
+pub fn write_streaming<I, W>(rows: I, mut output: W, options: &FormatOptions) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = Vec<Option<String>>>,
+    W: Write,
+{
+    let template: Template = match options.get("template") {
+        Some(template) => template.parse()?,
+        None => bail!("the template format requires OUTPUT_TEMPLATE"),
+    };
+    let line_ending = options.line_ending()?;
+    let final_newline = options.final_newline()?;
+    let flush_rows = options.flush_rows()?;
+
+    let mut rows = rows.into_iter();
+    // An empty result has no header to resolve the columns against, and nothing to write.
+    let Some(header) = rows.next() else {
+        return Ok(());
+    };
+    let indices = template.resolve(&header)?;
+    let mut line = String::new();
+    let mut wrote_rows = false;
+    for (index, row) in rows.enumerate() {
+        if is_interrupted() {
+            break;
+        }
+        line.clear();
+        if index > 0 {
+            line.push_str(line_ending);
+        }
+        template.render(&indices, &row, &mut line);
+        output.write_all(line.as_bytes())?;
+        wrote_rows = true;
+        if (index + 1) % flush_rows == 0 {
+            output.flush()?;
+        }
+    }
+    if wrote_rows && final_newline {
+        output.write_all(line_ending.as_bytes())?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, rows: &[&[Option<&str>]], options: &[(&str, &str)]) -> String {
+        let mut format_options = FormatOptions::default();
+        format_options.set("template", template);
+        for (name, value) in options {
+            format_options.set(*name, *value);
+        }
+        let rows = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.map(str::to_string)).collect());
+        let mut output = Vec::new();
+        write_streaming(rows, &mut output, &format_options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn expands_one_line_per_row() {
+        let rows: &[&[Option<&str>]] = &[
+            &[Some("id"), Some("name")],
+            &[Some("1"), Some("ada")],
+            &[Some("2"), None],
+        ];
+        assert_eq!(render("{{{id}}} {name}!", rows, &[]), "{1} ada!\n{2} !\n");
+        assert_eq!(
+            render(
+                "{id}",
+                rows,
+                &[("line_ending", "crlf"), ("final_newline", "false")]
+            ),
+            "1\r\n2"
+        );
+        assert_eq!(render("{id}", &rows[..1], &[]), "");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for (template, message) in [
+            ("{id", "unclosed placeholder {id in template"),
+            ("{}", "empty placeholder {} in template"),
+            (
+                "id}",
+                "unmatched } in template (write }} for a literal brace)",
+            ),
+        ] {
+            assert_eq!(
+                template.parse::<Template>().unwrap_err().to_string(),
+                message
+            );
+        }
+
+        let mut format_options = FormatOptions::default();
+        format_options.set("template", "{missing}");
+        let rows = vec![vec![Some("id".to_string())]];
+        let err = write_streaming(rows, Vec::new(), &format_options).unwrap_err();
+        assert_eq!(err.to_string(), "unknown column missing in template");
+    }
+}
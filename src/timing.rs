@@ -0,0 +1,152 @@
+use std::{fmt, io::Write, time::Duration};
+
+use mysql::serde_json::json;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseTimings {
+    pub connect: Duration,
+    pub query: Duration,
+    pub convert: Duration,
+    pub write: Duration,
+    pub total: Duration,
+}
+
+impl PhaseTimings {
+    pub fn write_json<W>(&self, mut output: W) -> anyhow::Result<()>
+    where
+        W: Write,
+    {
+        let timings = json!({
+            "connect_ms": self.connect.as_millis() as u64,
+            "query_ms": self.query.as_millis() as u64,
+            "convert_ms": self.convert.as_millis() as u64,
+            "write_ms": self.write.as_millis() as u64,
+            "total_ms": self.total.as_millis() as u64,
+        });
+        output.write_all(timings.to_string().as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Latency statistics over repeated runs of the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub iterations: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub median: Duration,
+}
+
+impl LatencyStats {
+    /// Aggregates the given latencies, or returns `None` if there are none. The median of an
+    /// even number of samples is the mean of the middle two.
+    pub fn from_durations(durations: &[Duration]) -> Option<Self> {
+        if durations.is_empty() {
+            return None;
+        }
+
+        let mut sorted = durations.to_vec();
+        sorted.sort_unstable();
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2
+        } else {
+            sorted[middle]
+        };
+
+        Some(LatencyStats {
+            iterations: sorted.len(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            median,
+        })
+    }
+
+    pub fn write_json<W>(&self, mut output: W) -> anyhow::Result<()>
+    where
+        W: Write,
+    {
+        let stats = json!({
+            "iterations": self.iterations,
+            "min_ms": self.min.as_secs_f64() * 1000.0,
+            "max_ms": self.max.as_secs_f64() * 1000.0,
+            "mean_ms": self.mean.as_secs_f64() * 1000.0,
+            "median_ms": self.median.as_secs_f64() * 1000.0,
+        });
+        output.write_all(stats.to_string().as_bytes())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} iterations: min={:?} max={:?} mean={:?} median={:?}",
+            self.iterations, self.min, self.max, self.mean, self.median
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mysql::serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn phase_timings_have_numeric_keys() {
+        let timings = PhaseTimings {
+            connect: Duration::from_millis(12),
+            query: Duration::from_millis(340),
+            convert: Duration::from_millis(5),
+            write: Duration::from_millis(7),
+            total: Duration::from_millis(364),
+        };
+        let mut output = Vec::new();
+        timings.write_json(&mut output).unwrap();
+
+        let json: Value = mysql::serde_json::from_slice(&output).unwrap();
+        for (key, expected) in [
+            ("connect_ms", 12),
+            ("query_ms", 340),
+            ("convert_ms", 5),
+            ("write_ms", 7),
+            ("total_ms", 364),
+        ] {
+            assert_eq!(json[key].as_u64(), Some(expected), "{}", key);
+        }
+    }
+
+    #[test]
+    fn aggregates_latencies() {
+        let millis = |values: &[u64]| -> Vec<Duration> {
+            values.iter().copied().map(Duration::from_millis).collect()
+        };
+        assert_eq!(LatencyStats::from_durations(&[]), None);
+
+        let stats = LatencyStats::from_durations(&millis(&[30, 10, 20])).unwrap();
+        assert_eq!(
+            stats,
+            LatencyStats {
+                iterations: 3,
+                min: Duration::from_millis(10),
+                max: Duration::from_millis(30),
+                mean: Duration::from_millis(20),
+                median: Duration::from_millis(20),
+            }
+        );
+
+        let stats = LatencyStats::from_durations(&millis(&[40, 10, 20, 10])).unwrap();
+        assert_eq!(stats.median, Duration::from_millis(15));
+        assert_eq!(stats.mean, Duration::from_millis(20));
+
+        let mut output = Vec::new();
+        stats.write_json(&mut output).unwrap();
+        let json: Value = mysql::serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["iterations"].as_u64(), Some(4));
+        assert_eq!(json["max_ms"].as_f64(), Some(40.0));
+    }
+}
@@ -0,0 +1,508 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use mysql::{prelude::Queryable, Error, Opts, Pool, PooledConn, SslOpts};
+
+use crate::connection::connect;
+
+#[cfg(feature = "ssl")]
+pub mod cert_utils;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsValidationMode {
+    /// Encrypt the connection but accept any server certificate.
+    AcceptInvalid,
+    /// Validate the certificate chain against the trusted CAs without checking the hostname.
+    SkipHostname,
+    /// Validate both the certificate chain and the hostname.
+    Full,
+}
+
+/// The `--ssl-mode` values understood by the `mysql` command line client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SslMode {
+    Disabled,
+    Required,
+    VerifyCa,
+    VerifyIdentity,
+}
+
+impl FromStr for SslMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().replace('-', "_").as_str() {
+            "DISABLED" => Ok(SslMode::Disabled),
+            "REQUIRED" => Ok(SslMode::Required),
+            "VERIFY_CA" => Ok(SslMode::VerifyCa),
+            "VERIFY_IDENTITY" => Ok(SslMode::VerifyIdentity),
+            other => Err(anyhow::anyhow!(
+                "unsupported ssl mode: {} (expected DISABLED, REQUIRED, VERIFY_CA or VERIFY_IDENTITY)",
+                other
+            )),
+        }
+    }
+}
+
+/// Takes an `ssl-mode` query parameter (also spelled `sslmode` or `ssl_mode`) out of a database
+/// URL such as `mysql://host/db?ssl-mode=VERIFY_IDENTITY`, returning the mode, if any, and the URL
+/// without it, since the driver rejects parameters it doesn't know.
+pub fn ssl_mode_from_url(url: &str) -> anyhow::Result<(Option<SslMode>, String)> {
+    let Some((base, query)) = url.split_once('?') else {
+        return Ok((None, url.to_string()));
+    };
+
+    let mut ssl_mode = None;
+    let mut params = Vec::new();
+    for param in query.split('&') {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        if matches!(
+            name.to_ascii_lowercase().as_str(),
+            "ssl-mode" | "sslmode" | "ssl_mode"
+        ) {
+            if ssl_mode.is_some() {
+                anyhow::bail!("the database URL sets the ssl mode more than once");
+            }
+            ssl_mode = Some(value.parse::<SslMode>()?);
+        } else {
+            params.push(param);
+        }
+    }
+
+    let url = if params.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}?{}", base, params.join("&"))
+    };
+    Ok((ssl_mode, url))
+}
+
+/// An explicit on/off switch for TLS, layered over the finer-grained ssl mode and CA settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsPolicy {
+    /// TLS is enabled by an ssl mode or CA file, and off otherwise.
+    Auto,
+    /// Always encrypt, failing if the server doesn't support TLS.
+    Require,
+    /// Always connect in plaintext.
+    Disable,
+}
+
+impl FromStr for TlsPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(TlsPolicy::Auto),
+            "require" | "required" => Ok(TlsPolicy::Require),
+            "disable" | "disabled" => Ok(TlsPolicy::Disable),
+            other => Err(anyhow::anyhow!(
+                "unsupported TLS policy: {} (expected auto, require or disable)",
+                other
+            )),
+        }
+    }
+}
+
+impl TlsPolicy {
+    /// Combines the policy with an explicit ssl mode, rejecting combinations that contradict
+    /// each other. `Require` alone means `REQUIRED`, or `VERIFY_CA` when a CA file is given.
+    pub fn resolve(
+        &self,
+        ssl_mode: Option<SslMode>,
+        has_ca_file: bool,
+    ) -> anyhow::Result<Option<SslMode>> {
+        match (self, ssl_mode) {
+            (TlsPolicy::Auto, ssl_mode) => Ok(ssl_mode),
+            (TlsPolicy::Require, Some(SslMode::Disabled)) => {
+                anyhow::bail!("TLS require cannot be combined with ssl mode DISABLED")
+            }
+            (TlsPolicy::Require, None) if !has_ca_file => Ok(Some(SslMode::Required)),
+            (TlsPolicy::Require, ssl_mode) => Ok(ssl_mode),
+            (TlsPolicy::Disable, _) if has_ca_file => {
+                anyhow::bail!("TLS disable cannot be combined with a CA file")
+            }
+            (TlsPolicy::Disable, Some(ssl_mode)) if ssl_mode != SslMode::Disabled => {
+                anyhow::bail!("TLS disable cannot be combined with an ssl mode other than DISABLED")
+            }
+            (TlsPolicy::Disable, _) => Ok(Some(SslMode::Disabled)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub mode: TlsValidationMode,
+    /// Extra CA certificates to trust. native-tls adds them on top of the platform trust store
+    /// rather than replacing it, so both internal and public CAs are accepted.
+    pub ca_file: Option<PathBuf>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            enabled: false,
+            mode: TlsValidationMode::Full,
+            ca_file: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Maps an ssl mode onto a TLS configuration. As with the `mysql` client, supplying a CA
+    /// file without an explicit mode implies `VERIFY_CA`.
+    pub fn new(ssl_mode: Option<SslMode>, ca_file: Option<PathBuf>) -> anyhow::Result<Self> {
+        let ssl_mode = match (ssl_mode, &ca_file) {
+            (Some(ssl_mode), _) => ssl_mode,
+            (None, Some(_)) => SslMode::VerifyCa,
+            (None, None) => return Ok(TlsConfig::default()),
+        };
+
+        let mode = match ssl_mode {
+            SslMode::Disabled if ca_file.is_some() => {
+                anyhow::bail!("a CA file cannot be used when the ssl mode is DISABLED")
+            }
+            SslMode::Disabled => return Ok(TlsConfig::default()),
+            SslMode::Required => TlsValidationMode::AcceptInvalid,
+            SslMode::VerifyCa => TlsValidationMode::SkipHostname,
+            SslMode::VerifyIdentity => TlsValidationMode::Full,
+        };
+
+        if !cfg!(feature = "ssl") {
+            anyhow::bail!("TLS support requires the ssl feature");
+        }
+
+        Ok(TlsConfig {
+            enabled: true,
+            mode,
+            ca_file,
+        })
+    }
+
+    /// Skips the hostname check, but keeps chain validation, when connecting to `exempt_host`: a
+    /// known host whose certificate doesn't name it. Connections to any other host are verified
+    /// as configured.
+    pub fn exempt_hostname(mut self, target_host: &str, exempt_host: &str) -> Self {
+        if self.mode == TlsValidationMode::Full && target_host.eq_ignore_ascii_case(exempt_host) {
+            self.mode = TlsValidationMode::SkipHostname;
+        }
+        self
+    }
+
+    pub fn to_ssl_opts(&self) -> Option<SslOpts> {
+        if !self.enabled {
+            return None;
+        }
+
+        Some(
+            SslOpts::default()
+                .with_root_cert_path(self.ca_file.clone())
+                .with_danger_skip_domain_validation(self.mode != TlsValidationMode::Full)
+                .with_danger_accept_invalid_certs(self.mode == TlsValidationMode::AcceptInvalid),
+        )
+    }
+}
+
+/// Asks the server which TLS version and cipher it negotiated for this session, returning `None`
+/// for a plaintext connection.
+pub fn negotiated_parameters<C>(conn: &mut C) -> anyhow::Result<Option<(String, String)>>
+where
+    C: Queryable,
+{
+    let status: Vec<(String, String)> =
+        conn.query("SHOW SESSION STATUS WHERE Variable_name IN ('Ssl_version', 'Ssl_cipher')")?;
+
+    let lookup = |name: &str| {
+        status
+            .iter()
+            .find(|(variable, _)| variable.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .filter(|value| !value.is_empty())
+    };
+
+    Ok(lookup("Ssl_version").zip(lookup("Ssl_cipher")))
+}
+
+pub fn format_negotiated_parameters(version: &str, cipher: &str) -> String {
+    format!("TLS negotiated: protocol {}, cipher {}", version, cipher)
+}
+
+/// Connects with [`connect`]. With `retry` set, a connection that fails in the TLS handshake,
+/// such as on a certificate the trusted CAs don't cover, is tried once more after reading
+/// `ca_file` again, in case a sidecar rotated the bundle while the export started.
+pub fn create_tls_connection(
+    opts: Opts,
+    retries: u32,
+    delay: Duration,
+    retry: bool,
+    ca_file: Option<&Path>,
+) -> anyhow::Result<(Pool, PooledConn)> {
+    match connect(opts.clone(), retries, delay) {
+        Err(_err) if retry && is_tls_handshake_error(&_err) => {
+            #[cfg(feature = "verbose")]
+            eprintln!(
+                "TLS handshake failed ({}), reloading the CA certificates and retrying.",
+                _err
+            );
+            reload_ca_file(ca_file)?;
+            // The first attempts already had their retries; this one is only for the new bundle.
+            Ok(connect(opts, 0, delay)?)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Whether a connection failed in the TLS handshake itself. Authentication, network, and other
+/// TLS errors don't count, since a new CA bundle can't fix them.
+pub fn is_tls_handshake_error(_err: &Error) -> bool {
+    #[cfg(feature = "ssl")]
+    {
+        use mysql::error::tls::TlsError;
+
+        matches!(_err, Error::TlsError(TlsError::TlsHandshakeError(_)))
+    }
+    #[cfg(not(feature = "ssl"))]
+    {
+        false
+    }
+}
+
+/// Reads the CA file again. The driver reads PEM and DER files itself on every connection, but a
+/// PKCS#7 bundle has to be converted again.
+#[cfg_attr(not(feature = "ssl"), allow(unused_variables))]
+fn reload_ca_file(ca_file: Option<&Path>) -> anyhow::Result<()> {
+    #[cfg(feature = "ssl")]
+    if let Some(path) = ca_file {
+        let certificates = cert_utils::validate_ca_file(path)?;
+        if cert_utils::is_pkcs7_file(path) {
+            cert_utils::write_ca_bundle(&certificates)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ssl_modes() {
+        assert_eq!(
+            "verify-identity".parse::<SslMode>().unwrap(),
+            SslMode::VerifyIdentity
+        );
+        assert_eq!("VERIFY_CA".parse::<SslMode>().unwrap(), SslMode::VerifyCa);
+        assert!("PREFERRED".parse::<SslMode>().is_err());
+    }
+
+    #[test]
+    fn disabled_mode_turns_tls_off() {
+        let config = TlsConfig::new(Some(SslMode::Disabled), None).unwrap();
+        assert!(!config.enabled);
+        assert!(config.to_ssl_opts().is_none());
+        assert!(!TlsConfig::new(None, None).unwrap().enabled);
+        assert!(TlsConfig::new(Some(SslMode::Disabled), Some(PathBuf::from("ca.pem"))).is_err());
+    }
+
+    #[cfg(feature = "ssl")]
+    #[test]
+    fn maps_ssl_modes_to_validation_modes() {
+        for (ssl_mode, mode) in [
+            (SslMode::Required, TlsValidationMode::AcceptInvalid),
+            (SslMode::VerifyCa, TlsValidationMode::SkipHostname),
+            (SslMode::VerifyIdentity, TlsValidationMode::Full),
+        ] {
+            let config = TlsConfig::new(Some(ssl_mode), None).unwrap();
+            assert!(config.enabled);
+            assert_eq!(config.mode, mode);
+        }
+    }
+
+    #[cfg(feature = "ssl")]
+    #[test]
+    fn ca_file_implies_verify_ca() {
+        let config = TlsConfig::new(None, Some(PathBuf::from("ca.pem"))).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.mode, TlsValidationMode::SkipHostname);
+        assert_eq!(config.ca_file, Some(PathBuf::from("ca.pem")));
+    }
+
+    #[test]
+    fn takes_the_ssl_mode_out_of_the_url() {
+        assert_eq!(
+            ssl_mode_from_url("mysql://u@db/app?ssl-mode=VERIFY_IDENTITY").unwrap(),
+            (
+                Some(SslMode::VerifyIdentity),
+                "mysql://u@db/app".to_string()
+            )
+        );
+        assert_eq!(
+            ssl_mode_from_url(
+                "mysql://u@db/app?compress=true&sslmode=required&prefer_socket=false"
+            )
+            .unwrap(),
+            (
+                Some(SslMode::Required),
+                "mysql://u@db/app?compress=true&prefer_socket=false".to_string()
+            )
+        );
+        assert_eq!(
+            ssl_mode_from_url("mysql://u@db/app?SSL_MODE=disabled").unwrap(),
+            (Some(SslMode::Disabled), "mysql://u@db/app".to_string())
+        );
+        assert_eq!(
+            ssl_mode_from_url("mysql://u@db/app?compress=true").unwrap(),
+            (None, "mysql://u@db/app?compress=true".to_string())
+        );
+        assert_eq!(
+            ssl_mode_from_url("mysql://u@db/app").unwrap(),
+            (None, "mysql://u@db/app".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_or_repeated_ssl_modes_in_the_url() {
+        assert!(ssl_mode_from_url("mysql://u@db/app?ssl-mode=sometimes").is_err());
+        let err =
+            ssl_mode_from_url("mysql://u@db/app?ssl-mode=REQUIRED&sslmode=DISABLED").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the database URL sets the ssl mode more than once"
+        );
+    }
+
+    #[test]
+    fn resolves_the_tls_policy() {
+        assert_eq!("Required".parse::<TlsPolicy>().unwrap(), TlsPolicy::Require);
+        assert!("on".parse::<TlsPolicy>().is_err());
+
+        let auto = TlsPolicy::Auto;
+        assert_eq!(auto.resolve(None, false).unwrap(), None);
+        assert_eq!(
+            auto.resolve(Some(SslMode::VerifyCa), false).unwrap(),
+            Some(SslMode::VerifyCa)
+        );
+
+        let require = TlsPolicy::Require;
+        assert_eq!(
+            require.resolve(None, false).unwrap(),
+            Some(SslMode::Required)
+        );
+        // The CA file then implies VERIFY_CA.
+        assert_eq!(require.resolve(None, true).unwrap(), None);
+        assert_eq!(
+            require
+                .resolve(Some(SslMode::VerifyIdentity), false)
+                .unwrap(),
+            Some(SslMode::VerifyIdentity)
+        );
+        assert!(require.resolve(Some(SslMode::Disabled), false).is_err());
+
+        let disable = TlsPolicy::Disable;
+        assert_eq!(
+            disable.resolve(None, false).unwrap(),
+            Some(SslMode::Disabled)
+        );
+        assert!(disable.resolve(None, true).is_err());
+        assert!(disable.resolve(Some(SslMode::Required), false).is_err());
+    }
+
+    #[test]
+    fn formats_negotiated_parameters() {
+        assert_eq!(
+            format_negotiated_parameters("TLSv1.3", "TLS_AES_256_GCM_SHA384"),
+            "TLS negotiated: protocol TLSv1.3, cipher TLS_AES_256_GCM_SHA384"
+        );
+    }
+
+    #[cfg(feature = "ssl")]
+    #[test]
+    fn exempts_only_the_named_host() {
+        let config = TlsConfig::new(Some(SslMode::VerifyIdentity), None).unwrap();
+        assert_eq!(
+            config
+                .clone()
+                .exempt_hostname("DB.internal", "db.internal")
+                .mode,
+            TlsValidationMode::SkipHostname
+        );
+        assert_eq!(
+            config.exempt_hostname("other.internal", "db.internal").mode,
+            TlsValidationMode::Full
+        );
+    }
+
+    #[test]
+    fn only_handshake_failures_are_retried_with_a_new_bundle() {
+        let access_denied = Error::MySqlError(mysql::MySqlError {
+            state: "28000".to_string(),
+            message: "Access denied for user 'app'@'%'".to_string(),
+            code: 1045,
+        });
+        assert!(!is_tls_handshake_error(&access_denied));
+        let refused = Error::IoError(std::io::ErrorKind::ConnectionRefused.into());
+        assert!(!is_tls_handshake_error(&refused));
+        let url = Opts::from_url("postgres://db").unwrap_err();
+        assert!(!is_tls_handshake_error(&Error::UrlError(url)));
+        #[cfg(feature = "ssl")]
+        assert!(is_tls_handshake_error(&untrusted_handshake_error()));
+    }
+
+    /// The error from a handshake with a local server whose self-signed certificate the client
+    /// doesn't trust.
+    #[cfg(feature = "ssl")]
+    fn untrusted_handshake_error() -> Error {
+        use std::{
+            net::{TcpListener, TcpStream},
+            thread,
+        };
+
+        use native_tls::{Identity, TlsAcceptor, TlsConnector};
+        use openssl::{
+            asn1::Asn1Time, hash::MessageDigest, pkey::PKey, rsa::Rsa, x509::X509NameBuilder,
+            x509::X509,
+        };
+
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let identity = Identity::from_pkcs8(
+            &builder.build().to_pem().unwrap(),
+            &key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // Fails as well, once the client rejects the certificate.
+            let _ = acceptor.accept(stream);
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        let err = TlsConnector::new()
+            .unwrap()
+            .connect("localhost", stream)
+            .unwrap_err();
+        server.join().unwrap();
+        Error::from(err)
+    }
+}
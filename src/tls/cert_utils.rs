@@ -0,0 +1,355 @@
+use std::{
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{bail, Context};
+use openssl::{
+    asn1::Asn1Time,
+    hash::MessageDigest,
+    pkcs7::Pkcs7,
+    x509::{X509NameRef, X509Ref, X509},
+};
+
+/// Loads the certificates in a CA file, telling PEM (`-----BEGIN`) from DER (a binary ASN.1
+/// sequence, starting with `0x30`) by its contents rather than its extension. Either may also hold
+/// a PKCS#7 bundle (`.p7b`/`.p7c`), as Windows exports them.
+pub fn load_ca_certificates(path: &Path) -> anyhow::Result<Vec<X509>> {
+    let file = File::open(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    read_ca_certificates(file, &path.display().to_string())
+}
+
+/// Reads PEM or DER certificates, or a PKCS#7 bundle, from any source; `source` names it in
+/// errors.
+pub fn read_ca_certificates<R>(mut reader: R, source: &str) -> anyhow::Result<Vec<X509>>
+where
+    R: Read,
+{
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .with_context(|| format!("couldn't read {}", source))?;
+    let text = data.trim_ascii_start();
+    if text.starts_with(b"-----BEGIN PKCS7-----") {
+        Pkcs7::from_pem(text)
+            .map(|bundle| pkcs7_certificates(&bundle))
+            .with_context(|| format!("couldn't parse {} as a PKCS#7 bundle", source))
+    } else if text.starts_with(b"-----BEGIN") {
+        X509::stack_from_pem(text)
+            .with_context(|| format!("couldn't parse {} as PEM certificates", source))
+    } else if data.first() == Some(&0x30) {
+        // A DER PKCS#7 bundle is an ASN.1 sequence too.
+        match X509::from_der(&data) {
+            Ok(certificate) => Ok(vec![certificate]),
+            Err(err) => match Pkcs7::from_der(&data) {
+                Ok(bundle) => Ok(pkcs7_certificates(&bundle)),
+                Err(_) => Err(err).with_context(|| {
+                    format!(
+                        "couldn't parse {} as a DER certificate or PKCS#7 bundle",
+                        source
+                    )
+                }),
+            },
+        }
+    } else {
+        bail!("{} is neither a PEM nor a DER certificate file", source)
+    }
+}
+
+/// Whether the file holds a PKCS#7 bundle, which the driver can't load directly; its
+/// certificates have to be rewritten as PEM with [`write_ca_bundle`] first.
+pub fn is_pkcs7_file(path: &Path) -> bool {
+    let Ok(data) = fs::read(path) else {
+        return false;
+    };
+    data.trim_ascii_start()
+        .starts_with(b"-----BEGIN PKCS7-----")
+        || (data.first() == Some(&0x30)
+            && X509::from_der(&data).is_err()
+            && Pkcs7::from_der(&data).is_ok())
+}
+
+fn pkcs7_certificates(bundle: &Pkcs7) -> Vec<X509> {
+    bundle
+        .signed()
+        .and_then(|signed| signed.certificates())
+        .map(|certificates| certificates.iter().map(X509Ref::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Loads the CA file and confirms it contains at least one certificate.
+pub fn validate_ca_file(path: &Path) -> anyhow::Result<Vec<X509>> {
+    let certificates = load_ca_certificates(path)?;
+    if certificates.is_empty() {
+        bail!("{} does not contain any certificates", path.display());
+    }
+    Ok(certificates)
+}
+
+/// Parses inline PEM certificates, e.g. from an environment variable, and confirms there is at
+/// least one.
+pub fn validate_ca_pem(pem: &str) -> anyhow::Result<Vec<X509>> {
+    if !pem.trim_start().starts_with("-----BEGIN") {
+        bail!("inline CA certificates must be PEM");
+    }
+    let certificates = read_ca_certificates(pem.as_bytes(), "inline CA certificates")?;
+    if certificates.is_empty() {
+        bail!("inline CA certificates do not contain any certificates");
+    }
+    Ok(certificates)
+}
+
+/// Where [`write_ca_bundle`] puts this process's certificates.
+pub fn inline_ca_path() -> PathBuf {
+    env::temp_dir().join(format!("gold_digger-ca-{}.pem", process::id()))
+}
+
+/// Writes certificates to a temporary PEM file, since the driver only loads CA certificates from
+/// a path. The caller removes it from [`inline_ca_path`] when done.
+pub fn write_ca_bundle(certificates: &[X509]) -> anyhow::Result<PathBuf> {
+    let path = inline_ca_path();
+    // Replace rather than follow anything already at the path, such as a planted symlink.
+    let _ = fs::remove_file(&path);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| format!("couldn't create {}", path.display()))?;
+    for certificate in certificates {
+        file.write_all(&certificate.to_pem()?)?;
+    }
+    Ok(path)
+}
+
+pub fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = String::from_utf8_lossy(entry.data().as_slice());
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// The certificate's SHA-256 fingerprint, as `openssl x509 -fingerprint -sha256` prints it.
+pub fn fingerprint(certificate: &X509Ref) -> anyhow::Result<String> {
+    Ok(format_fingerprint(
+        &certificate.digest(MessageDigest::sha256())?,
+    ))
+}
+
+/// Formats digest bytes as colon-separated uppercase hex, e.g. `AB:01:FF`.
+pub fn format_fingerprint(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertificateStatus {
+    Valid,
+    Expired,
+    NotYetValid,
+}
+
+impl fmt::Display for CertificateStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CertificateStatus::Valid => write!(f, "valid"),
+            CertificateStatus::Expired => write!(f, "EXPIRED"),
+            CertificateStatus::NotYetValid => write!(f, "NOT YET VALID"),
+        }
+    }
+}
+
+/// Checks the certificate's validity period against the current time.
+pub fn certificate_status(certificate: &X509Ref) -> anyhow::Result<CertificateStatus> {
+    let now = Asn1Time::days_from_now(0)?;
+    if certificate.not_after() < now {
+        Ok(CertificateStatus::Expired)
+    } else if certificate.not_before() > now {
+        Ok(CertificateStatus::NotYetValid)
+    } else {
+        Ok(CertificateStatus::Valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use openssl::{
+        hash::MessageDigest,
+        pkcs7::Pkcs7Flags,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        stack::Stack,
+        x509::{X509Builder, X509NameBuilder},
+    };
+
+    use super::*;
+
+    /// A self-signed certificate for `common_name`, valid from `not_before` to `not_after` days
+    /// from now (negative for the past).
+    fn certificate(common_name: &str, not_before: i64, not_after: i64) -> X509 {
+        key_and_certificate(common_name, not_before, not_after).1
+    }
+
+    fn key_and_certificate(
+        common_name: &str,
+        not_before: i64,
+        not_after: i64,
+    ) -> (PKey<Private>, X509) {
+        let day = 24 * 60 * 60;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::from_unix(now + not_before * day).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::from_unix(now + not_after * day).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (key, builder.build())
+    }
+
+    /// A PKCS#7 bundle carrying the certificates for both names.
+    fn pkcs7_bundle(first: &str, second: &str) -> Pkcs7 {
+        let (key, signer) = key_and_certificate(first, -1, 365);
+        let mut certificates = Stack::new().unwrap();
+        certificates.push(certificate(second, -1, 365)).unwrap();
+        Pkcs7::sign(&signer, &key, &certificates, b"", Pkcs7Flags::empty()).unwrap()
+    }
+
+    fn subjects(certificates: &[X509]) -> Vec<String> {
+        certificates
+            .iter()
+            .map(|certificate| format_name(certificate.subject_name()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_pem_and_der_certificates() {
+        let first = certificate("First CA", -1, 365);
+        let second = certificate("Second CA", -1, 365);
+        let mut pem = first.to_pem().unwrap();
+        pem.extend(second.to_pem().unwrap());
+        let certificates = read_ca_certificates(pem.as_slice(), "ca.pem").unwrap();
+        assert_eq!(subjects(&certificates), vec!["CN=First CA", "CN=Second CA"]);
+
+        let der = first.to_der().unwrap();
+        let certificates = read_ca_certificates(der.as_slice(), "ca.der").unwrap();
+        assert_eq!(subjects(&certificates), vec!["CN=First CA"]);
+    }
+
+    #[test]
+    fn reads_pkcs7_bundles() {
+        let bundle = pkcs7_bundle("First CA", "Second CA");
+        let pem = bundle.to_pem().unwrap();
+        let certificates = read_ca_certificates(pem.as_slice(), "ca.p7b").unwrap();
+        assert_eq!(certificates.len(), 2);
+        let mut names = subjects(&certificates);
+        names.sort();
+        assert_eq!(names, vec!["CN=First CA", "CN=Second CA"]);
+
+        let der = bundle.to_der().unwrap();
+        let certificates = read_ca_certificates(der.as_slice(), "ca.p7c").unwrap();
+        assert_eq!(certificates.len(), 2);
+    }
+
+    #[test]
+    fn detects_pkcs7_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = pkcs7_bundle("First CA", "Second CA");
+        let pem = dir.path().join("ca.p7b");
+        fs::write(&pem, bundle.to_pem().unwrap()).unwrap();
+        let der = dir.path().join("ca.p7c");
+        fs::write(&der, bundle.to_der().unwrap()).unwrap();
+        let certificate_der = dir.path().join("ca.der");
+        fs::write(
+            &certificate_der,
+            certificate("CA", -1, 365).to_der().unwrap(),
+        )
+        .unwrap();
+
+        assert!(is_pkcs7_file(&pem));
+        assert!(is_pkcs7_file(&der));
+        assert!(!is_pkcs7_file(&certificate_der));
+        assert!(!is_pkcs7_file(&dir.path().join("missing.p7b")));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let err = read_ca_certificates(&b"not a certificate"[..], "ca.crt").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ca.crt is neither a PEM nor a DER certificate file"
+        );
+        let err = read_ca_certificates(&[0x30, 0x03, 0x01, 0x02][..], "ca.der").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "couldn't parse ca.der as a DER certificate or PKCS#7 bundle"
+        );
+    }
+
+    #[test]
+    fn classifies_the_validity_period() {
+        let status = |not_before, not_after| {
+            certificate_status(&certificate("CA", not_before, not_after)).unwrap()
+        };
+        assert_eq!(status(-1, 365), CertificateStatus::Valid);
+        assert_eq!(status(-30, -1), CertificateStatus::Expired);
+        assert_eq!(status(1, 365), CertificateStatus::NotYetValid);
+        assert_eq!(CertificateStatus::NotYetValid.to_string(), "NOT YET VALID");
+    }
+
+    #[test]
+    fn reads_inline_pem() {
+        let pem = certificate("Inline CA", -1, 365).to_pem().unwrap();
+        let pem = format!("\n  {}", String::from_utf8(pem).unwrap());
+        let certificates = validate_ca_pem(&pem).unwrap();
+        assert_eq!(subjects(&certificates), vec!["CN=Inline CA"]);
+
+        let err = validate_ca_pem("MIIBkTCB+wIJ").unwrap_err();
+        assert_eq!(err.to_string(), "inline CA certificates must be PEM");
+        assert!(validate_ca_pem("-----BEGIN CERTIFICATE-----\n-----END CERTIFICATE-----").is_err());
+    }
+
+    #[test]
+    fn writes_the_bundle_over_an_existing_file() {
+        let certificates = vec![certificate("Bundled CA", -1, 365)];
+        fs::write(inline_ca_path(), "stale").unwrap();
+        let path = write_ca_bundle(&certificates).unwrap();
+        let written = load_ca_certificates(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(subjects(&written.unwrap()), vec!["CN=Bundled CA"]);
+    }
+
+    #[test]
+    fn fingerprints_the_der_encoding() {
+        assert_eq!(format_fingerprint(&[0xab, 0x01, 0xff]), "AB:01:FF");
+        let certificate = certificate("CA", -1, 365);
+        let digest = openssl::sha::sha256(&certificate.to_der().unwrap());
+        let fingerprint = fingerprint(&certificate).unwrap();
+        assert_eq!(fingerprint, format_fingerprint(&digest));
+        assert_eq!(fingerprint.len(), 32 * 3 - 1);
+    }
+}
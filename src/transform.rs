@@ -0,0 +1,686 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+/// How result columns that share a name are handled. Renaming is the default, so no format
+/// loses a column's values by accident.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateColumnPolicy {
+    Error,
+    #[default]
+    Rename,
+    First,
+    Last,
+}
+
+impl FromStr for DuplicateColumnPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(DuplicateColumnPolicy::Error),
+            "rename" => Ok(DuplicateColumnPolicy::Rename),
+            "first" => Ok(DuplicateColumnPolicy::First),
+            "last" => Ok(DuplicateColumnPolicy::Last),
+            other => Err(anyhow::anyhow!(
+                "unsupported duplicate column policy: {} (expected error, rename, first or last)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderCase {
+    #[default]
+    AsIs,
+    Lower,
+    Upper,
+}
+
+impl FromStr for HeaderCase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "as-is" => Ok(HeaderCase::AsIs),
+            "lower" => Ok(HeaderCase::Lower),
+            "upper" => Ok(HeaderCase::Upper),
+            other => Err(anyhow::anyhow!(
+                "unsupported header case: {} (expected as-is, lower or upper)",
+                other
+            )),
+        }
+    }
+}
+
+/// Folds the case of every name in the header row.
+pub fn apply_header_case(rows: &mut [Vec<Option<String>>], case: HeaderCase) {
+    let Some(header) = rows.first_mut() else {
+        return;
+    };
+    for name in header.iter_mut().flatten() {
+        match case {
+            HeaderCase::AsIs => {}
+            HeaderCase::Lower => *name = name.to_lowercase(),
+            HeaderCase::Upper => *name = name.to_uppercase(),
+        }
+    }
+}
+
+fn header_names(rows: &[Vec<Option<String>>]) -> Vec<String> {
+    rows.first()
+        .map(|header| {
+            header
+                .iter()
+                .map(|cell| cell.clone().unwrap_or_default())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Applies the duplicate column policy to the header row and, for `first`/`last`, drops the
+/// other occurrences' values from every row.
+pub fn resolve_duplicate_columns(
+    rows: &mut [Vec<Option<String>>],
+    policy: DuplicateColumnPolicy,
+) -> anyhow::Result<()> {
+    let names = header_names(rows);
+
+    match policy {
+        DuplicateColumnPolicy::Error => {
+            let mut seen = HashSet::new();
+            for name in &names {
+                if !seen.insert(name) {
+                    bail!("duplicate column name: {}", name);
+                }
+            }
+        }
+        DuplicateColumnPolicy::Rename => {
+            let mut taken: HashSet<String> = names.iter().cloned().collect();
+            let mut seen = HashSet::new();
+            for (index, name) in names.iter().enumerate() {
+                if seen.insert(name) {
+                    continue;
+                }
+                let mut suffix = 2;
+                let renamed = loop {
+                    let candidate = format!("{}_{}", name, suffix);
+                    if !taken.contains(&candidate) {
+                        break candidate;
+                    }
+                    suffix += 1;
+                };
+                taken.insert(renamed.clone());
+                rows[0][index] = Some(renamed);
+            }
+        }
+        DuplicateColumnPolicy::First | DuplicateColumnPolicy::Last => {
+            let mut seen = HashSet::new();
+            let mut keep = vec![false; names.len()];
+            let order: Vec<usize> = if policy == DuplicateColumnPolicy::First {
+                (0..names.len()).collect()
+            } else {
+                (0..names.len()).rev().collect()
+            };
+            for index in order {
+                keep[index] = seen.insert(&names[index]);
+            }
+
+            for row in rows.iter_mut() {
+                let mut column = 0;
+                row.retain(|_| {
+                    let retained = keep.get(column).copied().unwrap_or(true);
+                    column += 1;
+                    retained
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops data rows that repeat an earlier row, comparing all columns or only those named in
+/// `columns`, and keeping the first occurrence in the original order.
+pub fn distinct_rows(
+    rows: &mut Vec<Vec<Option<String>>>,
+    columns: Option<&[String]>,
+) -> anyhow::Result<()> {
+    let indices: Option<Vec<usize>> = match columns {
+        Some(columns) => {
+            let names = header_names(rows);
+            let mut indices = Vec::with_capacity(columns.len());
+            for column in columns {
+                match names.iter().position(|name| name == column) {
+                    Some(index) => indices.push(index),
+                    None => bail!("distinct column {} does not exist in the result", column),
+                }
+            }
+            Some(indices)
+        }
+        None => None,
+    };
+
+    let mut seen: HashSet<Vec<Option<String>>> = HashSet::new();
+    let mut index = 0;
+    rows.retain(|row| {
+        index += 1;
+        if index == 1 {
+            return true;
+        }
+        let key = match &indices {
+            Some(indices) => indices
+                .iter()
+                .map(|&column| row.get(column).cloned().flatten())
+                .collect(),
+            None => row.clone(),
+        };
+        seen.insert(key)
+    });
+    Ok(())
+}
+
+/// A column to sort by, written `column`, `column:asc`, or `column:desc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, descending) = match s.rsplit_once(':') {
+            Some((column, direction)) if direction.eq_ignore_ascii_case("asc") => (column, false),
+            Some((column, direction)) if direction.eq_ignore_ascii_case("desc") => (column, true),
+            _ => (s, false),
+        };
+        if column.is_empty() {
+            bail!("missing column name in sort key {}", s);
+        }
+        Ok(SortKey {
+            column: column.to_string(),
+            descending,
+        })
+    }
+}
+
+/// Parses a comma-separated list of sort keys, most significant first.
+pub fn parse_sort_keys(text: &str) -> anyhow::Result<Vec<SortKey>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(SortKey::from_str)
+        .collect()
+}
+
+/// Sorts the data rows by the keys, leaving the header in place. A column is compared
+/// numerically when every non-NULL value in it parses as a number, and as text otherwise; NULL
+/// sorts first in ascending order, as in MySQL. The sort is stable, so rows that tie on every key
+/// keep their order.
+pub fn sort_rows(rows: &mut [Vec<Option<String>>], keys: &[SortKey]) -> anyhow::Result<()> {
+    let names = header_names(rows);
+    let mut columns = Vec::with_capacity(keys.len());
+    for key in keys {
+        let index = match names.iter().position(|name| *name == key.column) {
+            Some(index) => index,
+            None => bail!("sort column {} does not exist in the result", key.column),
+        };
+        let numeric = rows.iter().skip(1).all(|row| {
+            row.get(index)
+                .cloned()
+                .flatten()
+                .is_none_or(|value| value.trim().parse::<f64>().is_ok())
+        });
+        columns.push((index, numeric, key.descending));
+    }
+
+    if let Some((_, data)) = rows.split_first_mut() {
+        data.sort_by(|a, b| {
+            columns
+                .iter()
+                .map(|&(index, numeric, descending)| {
+                    let a = a.get(index).and_then(Option::as_deref);
+                    let b = b.get(index).and_then(Option::as_deref);
+                    let ordering = match (a, b) {
+                        (Some(a), Some(b)) if numeric => {
+                            let a: f64 = a.trim().parse().unwrap_or_default();
+                            let b: f64 = b.trim().parse().unwrap_or_default();
+                            a.total_cmp(&b)
+                        }
+                        (a, b) => a.cmp(&b),
+                    };
+                    if descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+    Ok(())
+}
+
+/// Checks that every row has `width` columns, so delimited output stays aligned with its header.
+/// `label` names the rows in the error, which counts them from 1.
+pub fn check_column_count(
+    rows: &[Vec<Option<String>>],
+    width: usize,
+    label: &str,
+) -> anyhow::Result<()> {
+    match rows.iter().position(|row| row.len() != width) {
+        Some(index) => bail!(
+            "{} {} has {} columns, expected {}",
+            label,
+            index + 1,
+            rows[index].len(),
+            width
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Prepends a column named `name` numbering the data rows from 1. A name that is already taken is
+/// an error unless `force` is set, in which case the names are left to the duplicate policy.
+pub fn add_row_number_column(
+    rows: &mut [Vec<Option<String>>],
+    name: &str,
+    force: bool,
+) -> anyhow::Result<()> {
+    if !force && header_names(rows).iter().any(|column| column == name) {
+        bail!("row number column {} already exists in the result", name);
+    }
+    for (index, row) in rows.iter_mut().enumerate() {
+        let cell = if index == 0 {
+            name.to_string()
+        } else {
+            index.to_string()
+        };
+        row.insert(0, Some(cell));
+    }
+    Ok(())
+}
+
+/// Writes `text` into every NULL cell of the data rows.
+pub fn fill_nulls(rows: &mut [Vec<Option<String>>], text: &str) {
+    for cell in rows.iter_mut().skip(1).flatten() {
+        if cell.is_none() {
+            *cell = Some(text.to_string());
+        }
+    }
+}
+
+/// Turns a single-row result into `column,value` pairs, one row per original column. Results
+/// with more than one row are rejected unless `force` is set, in which case only the first row
+/// is pivoted.
+pub fn transpose(
+    rows: Vec<Vec<Option<String>>>,
+    force: bool,
+) -> anyhow::Result<Vec<Vec<Option<String>>>> {
+    let data_rows = rows.len().saturating_sub(1);
+    if data_rows > 1 && !force {
+        bail!(
+            "transpose requires exactly one row, the result has {}",
+            data_rows
+        );
+    }
+
+    let mut rows = rows.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let values = rows.next().unwrap_or_default();
+
+    let mut transposed = vec![vec![Some("column".to_string()), Some("value".to_string())]];
+    transposed.extend(
+        header
+            .into_iter()
+            .zip(values)
+            .map(|(column, value)| vec![column, value]),
+    );
+    Ok(transposed)
+}
+
+/// A partition's value in the split column, and its rows including the header.
+pub type Partition = (Option<String>, Vec<Vec<Option<String>>>);
+
+/// Groups the data rows by their value in `column`, in order of first appearance. Every
+/// partition starts with a copy of the header row.
+pub fn partition_by_column(
+    rows: Vec<Vec<Option<String>>>,
+    column: &str,
+) -> anyhow::Result<Vec<Partition>> {
+    let index = match header_names(&rows).iter().position(|name| name == column) {
+        Some(index) => index,
+        None => bail!("column {} does not exist in the result", column),
+    };
+
+    let mut rows = rows.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let mut partitions: Vec<Partition> = Vec::new();
+    let mut positions: HashMap<Option<String>, usize> = HashMap::new();
+    for row in rows {
+        let value = row.get(index).cloned().flatten();
+        let position = *positions.entry(value.clone()).or_insert_with(|| {
+            partitions.push((value, vec![header.clone()]));
+            partitions.len() - 1
+        });
+        partitions[position].1.push(row);
+    }
+    Ok(partitions)
+}
+
+/// Splits the data rows into chunks of at most `size` rows. Every chunk starts with a copy of the
+/// header row, and an empty result has no chunks.
+pub fn chunk_by_rows(rows: Vec<Vec<Option<String>>>, size: usize) -> Vec<Vec<Vec<Option<String>>>> {
+    let mut rows = rows.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let mut chunks = Vec::new();
+    loop {
+        let mut chunk = vec![header.clone()];
+        chunk.extend(rows.by_ref().take(size));
+        if chunk.len() == 1 {
+            break;
+        }
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(cells: &[&[&str]]) -> Vec<Vec<Option<String>>> {
+        cells
+            .iter()
+            .map(|row| row.iter().map(|cell| Some(cell.to_string())).collect())
+            .collect()
+    }
+
+    fn two_ids() -> Vec<Vec<Option<String>>> {
+        rows(&[&["id", "name", "id"], &["1", "a", "10"], &["2", "b", "20"]])
+    }
+
+    #[test]
+    fn fills_null_cells_of_the_data_rows() {
+        let mut data = vec![
+            vec![Some("id".to_string()), None],
+            vec![Some("1".to_string()), None],
+            vec![None, Some("b".to_string())],
+        ];
+        fill_nulls(&mut data, "\\N");
+        assert_eq!(
+            data,
+            [
+                vec![Some("id".to_string()), None],
+                vec![Some("1".to_string()), Some("\\N".to_string())],
+                vec![Some("\\N".to_string()), Some("b".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_repeated_rows() {
+        let mut data = rows(&[
+            &["id", "name"],
+            &["1", "a"],
+            &["2", "b"],
+            &["1", "a"],
+            &["1", "c"],
+        ]);
+        data.push(vec![None, None]);
+        data.push(vec![None, None]);
+        distinct_rows(&mut data, None).unwrap();
+        let mut expected = rows(&[&["id", "name"], &["1", "a"], &["2", "b"], &["1", "c"]]);
+        expected.push(vec![None, None]);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn drops_rows_repeating_the_named_columns() {
+        let mut data = rows(&[&["id", "name"], &["1", "a"], &["2", "a"], &["3", "b"]]);
+        distinct_rows(&mut data, Some(&["name".to_string()])).unwrap();
+        assert_eq!(data, rows(&[&["id", "name"], &["1", "a"], &["3", "b"]]));
+
+        let err = distinct_rows(&mut data, Some(&["missing".to_string()])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "distinct column missing does not exist in the result"
+        );
+    }
+
+    #[test]
+    fn parses_sort_keys() {
+        assert_eq!(
+            parse_sort_keys("region, total:DESC,name:asc,").unwrap(),
+            vec![
+                SortKey {
+                    column: "region".to_string(),
+                    descending: false
+                },
+                SortKey {
+                    column: "total".to_string(),
+                    descending: true
+                },
+                SortKey {
+                    column: "name".to_string(),
+                    descending: false
+                },
+            ]
+        );
+        assert_eq!(parse_sort_keys("a:b").unwrap()[0].column, "a:b");
+        assert!(parse_sort_keys(":desc").is_err());
+    }
+
+    #[test]
+    fn sorts_by_several_keys() {
+        let mut data = rows(&[
+            &["region", "total", "id"],
+            &["west", "9", "1"],
+            &["east", "10", "2"],
+            &["west", "10", "3"],
+            &["east", "9", "4"],
+            &["east", "10", "5"],
+        ]);
+        sort_rows(&mut data, &parse_sort_keys("region,total:desc").unwrap()).unwrap();
+        let ids: Vec<&str> = data.iter().map(|row| row[2].as_deref().unwrap()).collect();
+        // Numeric comparison puts 10 after 9, and the tied 2 and 5 keep their order.
+        assert_eq!(ids, ["id", "2", "5", "4", "3", "1"]);
+    }
+
+    #[test]
+    fn sorts_text_and_nulls_first() {
+        let mut data = rows(&[&["code"], &["b"], &["10"], &["a"]]);
+        data.push(vec![None]);
+        sort_rows(&mut data, &parse_sort_keys("code").unwrap()).unwrap();
+        assert_eq!(
+            data,
+            vec![
+                vec![Some("code".to_string())],
+                vec![None],
+                vec![Some("10".to_string())],
+                vec![Some("a".to_string())],
+                vec![Some("b".to_string())],
+            ]
+        );
+
+        let err = sort_rows(&mut data, &parse_sort_keys("missing").unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "sort column missing does not exist in the result"
+        );
+    }
+
+    #[test]
+    fn duplicate_columns_are_an_error() {
+        let mut data = two_ids();
+        let err = resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::Error).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate column name: id");
+    }
+
+    #[test]
+    fn folds_header_case() {
+        assert_eq!("UPPER".parse::<HeaderCase>().unwrap(), HeaderCase::Upper);
+        assert_eq!("as-is".parse::<HeaderCase>().unwrap(), HeaderCase::AsIs);
+        assert!("title".parse::<HeaderCase>().is_err());
+
+        let mut data = rows(&[&["Id", "userName"], &["Id", "x"]]);
+        apply_header_case(&mut data, HeaderCase::Lower);
+        // Only the header is folded.
+        assert_eq!(data, rows(&[&["id", "username"], &["Id", "x"]]));
+        apply_header_case(&mut data, HeaderCase::Upper);
+        assert_eq!(data[0], rows(&[&["ID", "USERNAME"]])[0]);
+    }
+
+    #[test]
+    fn names_folded_together_are_disambiguated() {
+        let mut data = rows(&[&["ID", "Name", "id"], &["1", "a", "10"]]);
+        apply_header_case(&mut data, HeaderCase::Lower);
+        resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::default()).unwrap();
+        assert_eq!(data, rows(&[&["id", "name", "id_2"], &["1", "a", "10"]]));
+
+        let mut data = rows(&[&["ID", "id"], &["1", "10"]]);
+        apply_header_case(&mut data, HeaderCase::Upper);
+        let err = resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::Error).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate column name: ID");
+    }
+
+    #[test]
+    fn duplicate_columns_are_renamed() {
+        let mut data = two_ids();
+        resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::default()).unwrap();
+        assert_eq!(
+            data,
+            rows(&[
+                &["id", "name", "id_2"],
+                &["1", "a", "10"],
+                &["2", "b", "20"]
+            ])
+        );
+
+        // A suffix that is already taken is skipped.
+        let mut data = rows(&[&["id", "id_2", "id"], &["1", "2", "3"]]);
+        resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::Rename).unwrap();
+        assert_eq!(data[0], rows(&[&["id", "id_2", "id_3"]])[0]);
+    }
+
+    #[test]
+    fn duplicate_columns_keep_the_first_or_last() {
+        let mut data = two_ids();
+        resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::First).unwrap();
+        assert_eq!(data, rows(&[&["id", "name"], &["1", "a"], &["2", "b"]]));
+
+        let mut data = two_ids();
+        resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::Last).unwrap();
+        assert_eq!(data, rows(&[&["name", "id"], &["a", "10"], &["b", "20"]]));
+    }
+
+    #[test]
+    fn transposes_a_single_row() {
+        let data = rows(&[&["id", "name"], &["1", "a"]]);
+        assert_eq!(
+            transpose(data, false).unwrap(),
+            rows(&[&["column", "value"], &["id", "1"], &["name", "a"]])
+        );
+    }
+
+    #[test]
+    fn transpose_rejects_several_rows_unless_forced() {
+        let data = rows(&[&["id", "name"], &["1", "a"], &["2", "b"]]);
+        let err = transpose(data.clone(), false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "transpose requires exactly one row, the result has 2"
+        );
+        assert_eq!(
+            transpose(data, true).unwrap(),
+            rows(&[&["column", "value"], &["id", "1"], &["name", "a"]])
+        );
+    }
+
+    #[test]
+    fn chunks_rows_with_headers() {
+        let mut data = rows(&[&["id"]]);
+        data.extend((1..=250).map(|id| vec![Some(id.to_string())]));
+
+        let chunks = chunk_by_rows(data, 100);
+        let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.len() - 1).collect();
+        assert_eq!(sizes, vec![100, 100, 50]);
+        for chunk in &chunks {
+            assert_eq!(chunk[0], vec![Some("id".to_string())]);
+        }
+        assert_eq!(chunks[2][1], vec![Some("201".to_string())]);
+        assert_eq!(chunks[2][50], vec![Some("250".to_string())]);
+    }
+
+    #[test]
+    fn chunks_nothing_for_an_empty_result() {
+        assert!(chunk_by_rows(rows(&[&["id"]]), 100).is_empty());
+    }
+
+    #[test]
+    fn partitions_rows_by_column() {
+        let mut data = rows(&[
+            &["region", "id"],
+            &["emea", "1"],
+            &["apac", "2"],
+            &["emea", "3"],
+        ]);
+        data.push(vec![None, Some("4".to_string())]);
+
+        let partitions = partition_by_column(data, "region").unwrap();
+        let values: Vec<Option<&str>> = partitions
+            .iter()
+            .map(|(value, _)| value.as_deref())
+            .collect();
+        assert_eq!(values, vec![Some("emea"), Some("apac"), None]);
+        assert_eq!(
+            partitions[0].1,
+            rows(&[&["region", "id"], &["emea", "1"], &["emea", "3"]])
+        );
+        assert_eq!(partitions[1].1, rows(&[&["region", "id"], &["apac", "2"]]));
+        assert_eq!(partitions[2].1.len(), 2);
+        assert!(partition_by_column(rows(&[&["id"]]), "region").is_err());
+    }
+
+    #[test]
+    fn reports_the_first_row_with_the_wrong_width() {
+        let data = rows(&[&["1", "a"], &["2"], &["3", "c", "x"]]);
+        assert!(check_column_count(&data[..1], 2, "row").is_ok());
+        let err = check_column_count(&data, 2, "row").unwrap_err();
+        assert_eq!(err.to_string(), "row 2 has 1 columns, expected 2");
+        let err = check_column_count(&data[2..], 2, "APPEND_ROWS line").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "APPEND_ROWS line 1 has 3 columns, expected 2"
+        );
+    }
+
+    #[test]
+    fn numbers_rows_from_one() {
+        let mut data = rows(&[&["name"], &["a"], &["b"]]);
+        add_row_number_column(&mut data, "n", false).unwrap();
+        assert_eq!(data, rows(&[&["n", "name"], &["1", "a"], &["2", "b"]]));
+    }
+
+    #[test]
+    fn forced_row_numbers_leave_the_clash_to_the_duplicate_policy() {
+        let mut data = rows(&[&["id", "name"], &["7", "a"]]);
+        let err = add_row_number_column(&mut data, "id", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "row number column id already exists in the result"
+        );
+
+        add_row_number_column(&mut data, "id", true).unwrap();
+        resolve_duplicate_columns(&mut data, DuplicateColumnPolicy::default()).unwrap();
+        assert_eq!(data, rows(&[&["id", "id_2", "name"], &["1", "7", "a"]]));
+    }
+}
@@ -0,0 +1,330 @@
+use std::str::FromStr;
+
+use mysql::{consts::ColumnType, Value};
+
+/// How text values that aren't valid UTF-8 are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryEncoding {
+    /// Invalid sequences become U+FFFD replacement characters.
+    #[default]
+    Lossy,
+    /// The raw bytes in debug form, e.g. `[255, 254]`, as written before 0.3.
+    Debug,
+}
+
+impl FromStr for BinaryEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lossy" => Ok(BinaryEncoding::Lossy),
+            "debug" => Ok(BinaryEncoding::Debug),
+            other => Err(anyhow::anyhow!(
+                "unsupported binary encoding: {} (expected lossy or debug)",
+                other
+            )),
+        }
+    }
+}
+
+/// Renders a MySQL value as a cell, returning `None` for a cell that should stay SQL NULL.
+pub trait ValueFormatter {
+    fn format(&self, value: &Value) -> Option<String>;
+
+    /// Renders a value from a column of the given type. The text protocol delivers every value as
+    /// text, so rendering that depends on the column type, rather than the value, goes here.
+    fn format_column(&self, value: &Value, _column_type: ColumnType) -> Option<String> {
+        self.format(value)
+    }
+}
+
+/// The standard cell rendering. Its default writes dates as `YYYY-MM-DD HH:MM:SS[.ffffff]`,
+/// times as `[-]HH:MM:SS[.ffffff]` (hours may exceed 24), and keeps NULL as `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CellFormatter {
+    /// Text written for SQL NULL instead of leaving the cell NULL.
+    pub null_text: Option<String>,
+    /// A `strftime`-style pattern for date and datetime values, supporting `%Y`, `%m`, `%d`,
+    /// `%H`, `%M`, `%S`, `%f` (microseconds), and `%%`.
+    pub datetime_format: Option<String>,
+    pub binary_encoding: BinaryEncoding,
+    /// Strip leading and trailing whitespace from text values.
+    pub trim: bool,
+    /// Write FLOAT and DOUBLE values with this many decimal places, instead of the shortest text
+    /// that round-trips.
+    pub float_precision: Option<usize>,
+}
+
+impl ValueFormatter for CellFormatter {
+    fn format(&self, value: &Value) -> Option<String> {
+        let text = match value {
+            Value::NULL => return self.null_text.clone(),
+            Value::Bytes(bytes) => match std::str::from_utf8(bytes) {
+                Ok(text) => text.to_string(),
+                Err(_) if self.binary_encoding == BinaryEncoding::Debug => {
+                    format!("{:?}", bytes)
+                }
+                Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+            },
+            Value::Int(i) => i.to_string(),
+            Value::UInt(u) => u.to_string(),
+            Value::Float(f) => match self.float_precision {
+                Some(precision) => format!("{:.*}", precision, f),
+                None => f.to_string(),
+            },
+            Value::Double(d) => match self.float_precision {
+                Some(precision) => format!("{:.*}", precision, d),
+                None => d.to_string(),
+            },
+            Value::Date(year, month, day, hour, minute, second, micros) => {
+                match &self.datetime_format {
+                    Some(pattern) => format_datetime(
+                        pattern,
+                        [
+                            u32::from(*year),
+                            u32::from(*month),
+                            u32::from(*day),
+                            u32::from(*hour),
+                            u32::from(*minute),
+                            u32::from(*second),
+                        ],
+                        *micros,
+                    ),
+                    None => {
+                        let mut text = format!(
+                            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                            year, month, day, hour, minute, second
+                        );
+                        if *micros > 0 {
+                            text.push_str(&format!(".{:06}", micros));
+                        }
+                        text
+                    }
+                }
+            }
+            Value::Time(negative, days, hours, minutes, seconds, micros) => {
+                let mut text = format!(
+                    "{}{:02}:{:02}:{:02}",
+                    if *negative { "-" } else { "" },
+                    days * 24 + u32::from(*hours),
+                    minutes,
+                    seconds
+                );
+                if *micros > 0 {
+                    text.push_str(&format!(".{:06}", micros));
+                }
+                text
+            }
+        };
+
+        if self.trim && matches!(value, Value::Bytes(_)) {
+            Some(text.trim().to_string())
+        } else {
+            Some(text)
+        }
+    }
+
+    fn format_column(&self, value: &Value, column_type: ColumnType) -> Option<String> {
+        // Floats arrive as text over the text protocol; parse them so the precision applies.
+        if let (
+            Some(_),
+            Value::Bytes(bytes),
+            ColumnType::MYSQL_TYPE_FLOAT | ColumnType::MYSQL_TYPE_DOUBLE,
+        ) = (self.float_precision, value, column_type)
+        {
+            if let Some(d) = std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|text| text.parse().ok())
+            {
+                return self.format(&Value::Double(d));
+            }
+        }
+        // The binary protocol sends DATE values with a zero time; write them as the text protocol
+        // does.
+        if let (
+            None,
+            Value::Date(year, month, day, ..),
+            ColumnType::MYSQL_TYPE_DATE | ColumnType::MYSQL_TYPE_NEWDATE,
+        ) = (&self.datetime_format, value, column_type)
+        {
+            return Some(format!("{:04}-{:02}-{:02}", year, month, day));
+        }
+        self.format(value)
+    }
+}
+
+/// Expands a `strftime`-style pattern; `fields` holds year, month, day, hour, minute, and second.
+/// Unsupported specifiers are written unchanged.
+fn format_datetime(pattern: &str, fields: [u32; 6], micros: u32) -> String {
+    let [year, month, day, hour, minute, second] = fields;
+    let mut text = String::with_capacity(pattern.len() + 8);
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => text.push_str(&format!("{:04}", year)),
+            Some('m') => text.push_str(&format!("{:02}", month)),
+            Some('d') => text.push_str(&format!("{:02}", day)),
+            Some('H') => text.push_str(&format!("{:02}", hour)),
+            Some('M') => text.push_str(&format!("{:02}", minute)),
+            Some('S') => text.push_str(&format!("{:02}", second)),
+            Some('f') => text.push_str(&format!("{:06}", micros)),
+            Some('%') => text.push('%'),
+            Some(other) => {
+                text.push('%');
+                text.push(other);
+            }
+            None => text.push('%'),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_utf8_is_lossy_by_default() {
+        let value = Value::Bytes(b"caf\xe9 \xff".to_vec());
+        let formatter = CellFormatter::default();
+        assert_eq!(
+            formatter.format(&value).as_deref(),
+            Some("caf\u{fffd} \u{fffd}")
+        );
+
+        let formatter = CellFormatter {
+            binary_encoding: "DEBUG".parse().unwrap(),
+            ..CellFormatter::default()
+        };
+        assert_eq!(
+            formatter.format(&value).as_deref(),
+            Some("[99, 97, 102, 233, 32, 255]")
+        );
+        assert_eq!(
+            formatter.format(&Value::Bytes(b"ok".to_vec())).as_deref(),
+            Some("ok")
+        );
+        assert!("base64".parse::<BinaryEncoding>().is_err());
+    }
+
+    // 3.14159 is a sample value, not an attempt at PI.
+    #[allow(clippy::approx_constant)]
+    #[test]
+    fn float_precision_fixes_the_decimal_places() {
+        let formatter = |float_precision| CellFormatter {
+            float_precision,
+            ..CellFormatter::default()
+        };
+        let value = Value::Double(3.14159);
+        assert_eq!(formatter(Some(2)).format(&value).as_deref(), Some("3.14"));
+        assert_eq!(formatter(Some(4)).format(&value).as_deref(), Some("3.1416"));
+        assert_eq!(formatter(None).format(&value).as_deref(), Some("3.14159"));
+        assert_eq!(
+            formatter(Some(2)).format(&Value::Float(0.5)).as_deref(),
+            Some("0.50")
+        );
+    }
+
+    #[test]
+    fn float_precision_applies_to_text_protocol_floats() {
+        let formatter = CellFormatter {
+            float_precision: Some(2),
+            ..CellFormatter::default()
+        };
+        let text = Value::Bytes(b"3.14159".to_vec());
+        assert_eq!(
+            formatter
+                .format_column(&text, ColumnType::MYSQL_TYPE_DOUBLE)
+                .as_deref(),
+            Some("3.14")
+        );
+        assert_eq!(
+            formatter
+                .format_column(&text, ColumnType::MYSQL_TYPE_FLOAT)
+                .as_deref(),
+            Some("3.14")
+        );
+        // DECIMAL keeps its exact digits.
+        assert_eq!(
+            formatter
+                .format_column(&text, ColumnType::MYSQL_TYPE_NEWDECIMAL)
+                .as_deref(),
+            Some("3.14159")
+        );
+    }
+
+    #[test]
+    fn formats_dates_times_and_null() {
+        let formatter = CellFormatter::default();
+        let datetime = Value::Date(2024, 3, 7, 9, 5, 1, 0);
+        assert_eq!(
+            formatter.format(&datetime).as_deref(),
+            Some("2024-03-07 09:05:01")
+        );
+        assert_eq!(
+            formatter
+                .format(&Value::Date(2024, 3, 7, 9, 5, 1, 250))
+                .as_deref(),
+            Some("2024-03-07 09:05:01.000250")
+        );
+        assert_eq!(
+            formatter
+                .format(&Value::Time(true, 1, 2, 3, 4, 0))
+                .as_deref(),
+            Some("-26:03:04")
+        );
+        assert_eq!(
+            formatter
+                .format_column(&datetime, ColumnType::MYSQL_TYPE_DATE)
+                .as_deref(),
+            Some("2024-03-07")
+        );
+        assert_eq!(formatter.format(&Value::NULL), None);
+
+        let formatter = CellFormatter {
+            null_text: Some("\\N".to_string()),
+            datetime_format: Some("%d/%m/%Y %H:%M %% %q".to_string()),
+            trim: true,
+            ..CellFormatter::default()
+        };
+        assert_eq!(formatter.format(&Value::NULL).as_deref(), Some("\\N"));
+        assert_eq!(
+            formatter.format(&datetime).as_deref(),
+            Some("07/03/2024 09:05 % %q")
+        );
+        assert_eq!(
+            formatter
+                .format(&Value::Bytes(b"  padded ".to_vec()))
+                .as_deref(),
+            Some("padded")
+        );
+    }
+
+    #[test]
+    fn custom_formatters_replace_the_rendering() {
+        struct Upper;
+
+        impl ValueFormatter for Upper {
+            fn format(&self, value: &Value) -> Option<String> {
+                CellFormatter::default()
+                    .format(value)
+                    .map(|text| text.to_uppercase())
+            }
+        }
+
+        assert_eq!(
+            Upper.format(&Value::Bytes(b"abc".to_vec())).as_deref(),
+            Some("ABC")
+        );
+        assert_eq!(
+            Upper
+                .format_column(&Value::Int(7), ColumnType::MYSQL_TYPE_LONG)
+                .as_deref(),
+            Some("7")
+        );
+    }
+}
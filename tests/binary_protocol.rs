@@ -0,0 +1,43 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+/// Exports `query` over the text protocol and over the binary protocol, returning both outputs.
+fn both_protocols(query: &str, extension: &str) -> (String, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut outputs = Vec::new();
+    for binary in ["false", "true"] {
+        let output_file = dir.path().join(format!("{}.{}", binary, extension));
+        let status = common::gold_digger(query, output_file.to_str().unwrap())
+            .env("BINARY_PROTOCOL", binary)
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(0), "BINARY_PROTOCOL={}", binary);
+        outputs.push(fs::read_to_string(&output_file).unwrap());
+    }
+    let binary = outputs.pop().unwrap();
+    (outputs.pop().unwrap(), binary)
+}
+
+#[test]
+fn binary_protocol_matches_the_text_protocol() {
+    common::create_table(
+        "gd_binary_protocol",
+        "id BIGINT UNSIGNED PRIMARY KEY, small TINYINT, ratio DOUBLE, price DECIMAL(10, 2), \
+         day DATE, at DATETIME, span TIME, name VARCHAR(64), flag BIT(1), note TEXT",
+        20,
+        "n, n - 10, n / 8, n * 1.25, DATE_ADD('2024-01-01', INTERVAL n DAY), \
+         TIMESTAMP('2024-01-01 12:00:00') + INTERVAL n HOUR, SEC_TO_TIME(n * 61), \
+         CONCAT('name-', n), n % 2, IF(n % 3 = 0, NULL, REPEAT('x', n))",
+    );
+    let query = "SELECT * FROM gd_binary_protocol ORDER BY id";
+
+    let (text, binary) = both_protocols(query, "csv");
+    assert_eq!(text.lines().count(), 21);
+    assert_eq!(text, binary);
+
+    let (text, binary) = both_protocols(query, "json");
+    assert_eq!(text, binary);
+}
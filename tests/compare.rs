@@ -0,0 +1,28 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn empty_results_are_compared_too() {
+    common::create_table("gd_compare", "id INT PRIMARY KEY", 0, "n");
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.json");
+    let reference = dir.path().join("expected.json");
+    let compare = || {
+        common::gold_digger("SELECT id FROM gd_compare", output_file.to_str().unwrap())
+            .env("EXIT_ZERO_ON_NO_ROWS", "true")
+            .env("COMPARE_TO", &reference)
+            .output()
+            .unwrap()
+    };
+
+    fs::write(&reference, "{\"data\":[{\"id\":\"1\"}]}\n").unwrap();
+    let output = compare();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("+{\"data\":[]}"));
+
+    fs::write(&reference, "{\"data\":[]}\n").unwrap();
+    assert_eq!(compare().status.code(), Some(0));
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use mysql::prelude::Queryable;
+
+/// The test server's URL with `ssl-mode` added, which only gold_digger understands.
+fn with_ssl_mode(url: &str, ssl_mode: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}ssl-mode={}", url, separator, ssl_mode)
+}
+
+#[test]
+fn copies_rows_into_another_table() {
+    common::create_table(
+        "gd_copy_source",
+        "id INT PRIMARY KEY, name VARCHAR(64)",
+        25,
+        "n, IF(n % 5 = 0, NULL, CONCAT('name-', n))",
+    );
+    let mut conn = common::create_table(
+        "gd_copy_target",
+        "id INT PRIMARY KEY, name VARCHAR(64)",
+        0,
+        "n",
+    );
+
+    let status = common::gold_digger("SELECT id, name FROM gd_copy_source", "/dev/null")
+        .env("GOLD_DIGGER_DEFAULT_FORMAT", "csv")
+        .env(
+            "TO_DB_URL",
+            with_ssl_mode(&common::database_url(), "DISABLED"),
+        )
+        .env("TO_TABLE", "gd_copy_target")
+        .env("SQL_BATCH_SIZE", "10")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+
+    let (rows, nulls): (u64, u64) = conn
+        .query_first("SELECT COUNT(*), SUM(name IS NULL) FROM gd_copy_target")
+        .unwrap()
+        .unwrap();
+    assert_eq!((rows, nulls), (25, 5));
+}
+
+#[test]
+fn rejects_an_invalid_ssl_mode_in_the_target_url() {
+    let status = common::gold_digger("SELECT 1", "/dev/null")
+        .env("GOLD_DIGGER_DEFAULT_FORMAT", "csv")
+        .env(
+            "TO_DB_URL",
+            with_ssl_mode(&common::database_url(), "SOMETIMES"),
+        )
+        .env("TO_TABLE", "gd_copy_target")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(255));
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+use mysql::prelude::Queryable;
+
+#[test]
+fn reports_the_rows_a_statement_affected() {
+    let mut conn = common::create_table("gd_dml", "id INT PRIMARY KEY, v INT", 5, "n, n");
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+
+    for (query, affected) in [
+        (
+            "UPDATE gd_dml SET v = v + 1 WHERE id <= 3",
+            "3 rows affected.",
+        ),
+        // The affected rows are those of the last statement.
+        (
+            "SET @cutoff = 2; DELETE FROM gd_dml WHERE id <= @cutoff",
+            "2 rows affected.",
+        ),
+    ] {
+        let output = common::gold_digger(query, output_file.to_str().unwrap())
+            .env("ALLOW_DML", "true")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(0), "{}", stderr);
+        assert!(stderr.contains(affected), "{}", stderr);
+        assert!(!output_file.exists());
+    }
+
+    let remaining: Vec<(i32, i32)> = conn.query("SELECT id, v FROM gd_dml ORDER BY id").unwrap();
+    assert_eq!(remaining, [(3, 4), (4, 4), (5, 5)]);
+}
+
+#[test]
+fn exports_the_rows_of_a_script_that_also_selects() {
+    common::create_table("gd_dml_select", "id INT PRIMARY KEY, v INT", 3, "n, n");
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+
+    let output = common::gold_digger(
+        "UPDATE gd_dml_select SET v = v * 10; SELECT id, v FROM gd_dml_select ORDER BY id",
+        output_file.to_str().unwrap(),
+    )
+    .env("ALLOW_DML", "true")
+    .env("FORMAT_OPTION_QUOTE", "necessary")
+    .output()
+    .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(!stderr.contains("rows affected"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(&output_file).unwrap(),
+        "id,v\n1,10\n2,20\n3,30\n"
+    );
+}
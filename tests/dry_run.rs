@@ -0,0 +1,135 @@
+use std::{fs, process::Command};
+
+fn dry_run(query: &str, output_file: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_gold_digger"));
+    command
+        .env("DRY_RUN", "true")
+        .env("DATABASE_URL", "mysql://user@127.0.0.1:1/db")
+        .env("DATABASE_QUERY", query)
+        .env("OUTPUT_FILE", output_file);
+    command
+}
+
+#[test]
+fn valid_configuration_exits_zero() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let status = dry_run("SELECT 'a;b' AS x", output_file.to_str().unwrap())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    // Checking the output path must not leave a file behind.
+    assert!(!output_file.exists());
+}
+
+#[test]
+fn unbalanced_quote_is_a_config_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let output = dry_run("SELECT 'unterminated", output_file.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(255));
+    #[cfg(feature = "verbose")]
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unterminated single-quoted string"));
+}
+
+#[test]
+fn missing_extension_is_a_config_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out");
+    let status = dry_run("SELECT 1", output_file.to_str().unwrap())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(255));
+}
+
+#[test]
+fn default_format_covers_a_missing_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out");
+    let status = dry_run("SELECT 1", output_file.to_str().unwrap())
+        .env("GOLD_DIGGER_DEFAULT_FORMAT", "csv")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+
+    let status = dry_run("SELECT 1", output_file.to_str().unwrap())
+        .env("GOLD_DIGGER_DEFAULT_FORMAT", "xml")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(255));
+}
+
+#[test]
+fn prefixed_query_file_wins() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let query_file = dir.path().join("query.sql");
+    fs::write(&query_file, "SELECT 'unterminated").unwrap();
+
+    // The broken prefixed query file is used over the valid unprefixed query.
+    let status = dry_run("SELECT 1", output_file.to_str().unwrap())
+        .env("GOLD_DIGGER_ENV_PREFIX", "REPORTING")
+        .env("REPORTING_DATABASE_QUERY_FILE", &query_file)
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(255));
+
+    // Without the prefix it is ignored.
+    let status = dry_run("SELECT 1", output_file.to_str().unwrap())
+        .env("REPORTING_DATABASE_QUERY_FILE", &query_file)
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_output_is_refused_on_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("target.csv");
+    let output_file = dir.path().join("out.csv");
+    fs::write(&target, "kept\n").unwrap();
+    std::os::unix::fs::symlink(&target, &output_file).unwrap();
+
+    let status = dry_run("SELECT 1", output_file.to_str().unwrap())
+        .env("NO_FOLLOW_SYMLINKS", "true")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(255));
+    let status = dry_run("SELECT 1", output_file.to_str().unwrap())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&target).unwrap(), "kept\n");
+}
+
+#[test]
+fn password_sources_are_checked_up_front() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let password_file = dir.path().join("password");
+    fs::write(&password_file, "secret\n").unwrap();
+    let run = |command: &mut Command| command.status().unwrap().code();
+
+    let mut command = dry_run("SELECT 1", output_file.to_str().unwrap());
+    assert_eq!(run(command.env("PASSWORD_FILE", &password_file)), Some(0));
+
+    let mut command = dry_run("SELECT 1", output_file.to_str().unwrap());
+    command
+        .env("PASSWORD_FILE", &password_file)
+        .env("PASSWORD_ENV", "GD_TEST_PASSWORD");
+    assert_eq!(run(&mut command), Some(255));
+
+    let mut command = dry_run("SELECT 1", output_file.to_str().unwrap());
+    command.env("PASSWORD_FILE", dir.path().join("missing"));
+    assert_eq!(run(&mut command), Some(255));
+
+    let mut command = dry_run("SELECT 1", output_file.to_str().unwrap());
+    command
+        .env("PASSWORD_ENV", "GD_TEST_PASSWORD")
+        .env_remove("GD_TEST_PASSWORD");
+    assert_eq!(run(&mut command), Some(255));
+    assert_eq!(run(command.env("GD_TEST_PASSWORD", "secret")), Some(0));
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+const EMPTY_QUERY: &str = "SELECT 1 AS id FROM DUAL WHERE FALSE";
+
+#[test]
+fn output_if_nonempty_leaves_the_file_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    fs::write(&output_file, "id\n1\n").unwrap();
+
+    let status = common::gold_digger(EMPTY_QUERY, output_file.to_str().unwrap())
+        .env("OUTPUT_IF_NONEMPTY", "true")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&output_file).unwrap(), "id\n1\n");
+
+    let missing = dir.path().join("missing.csv");
+    let status = common::gold_digger(EMPTY_QUERY, missing.to_str().unwrap())
+        .env("OUTPUT_IF_NONEMPTY", "true")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert!(!missing.exists());
+}
+
+#[test]
+fn exit_codes_for_an_empty_result() {
+    // (flags, exit code, what is left in a file that held an earlier export)
+    let cases: [(&[&str], i32, Option<&str>); 6] = [
+        (&[], 1, Some("old\n")),
+        (&["EXIT_ZERO_ON_NO_ROWS"], 0, Some("")),
+        (&["ALLOW_EMPTY"], 0, Some("")),
+        (&["OUTPUT_IF_NONEMPTY"], 0, Some("old\n")),
+        (
+            &["EXIT_ZERO_ON_NO_ROWS", "OUTPUT_IF_NONEMPTY"],
+            0,
+            Some("old\n"),
+        ),
+        (
+            &["EXIT_ZERO_ON_NO_ROWS", "ATOMIC_OUTPUT", "FSYNC"],
+            0,
+            Some(""),
+        ),
+    ];
+    for (flags, code, contents) in cases {
+        let dir = tempfile::tempdir().unwrap();
+        let output_file = dir.path().join("out.csv");
+        fs::write(&output_file, "old\n").unwrap();
+        let mut command = common::gold_digger(EMPTY_QUERY, output_file.to_str().unwrap());
+        for flag in flags {
+            command.env(flag, "true");
+        }
+        let status = command.status().unwrap();
+        assert_eq!(status.code(), Some(code), "{:?}", flags);
+        assert_eq!(
+            fs::read_to_string(&output_file).ok().as_deref(),
+            contents,
+            "{:?}",
+            flags
+        );
+        // Nothing but the output itself, so no temporary file was left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1, "{:?}", flags);
+    }
+}
+
+#[test]
+fn empty_json_keeps_its_root_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.json");
+    let status = common::gold_digger(EMPTY_QUERY, output_file.to_str().unwrap())
+        .env("EXIT_ZERO_ON_NO_ROWS", "true")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&output_file).unwrap(), "{\"data\":[]}\n");
+
+    let status = common::gold_digger(EMPTY_QUERY, output_file.to_str().unwrap())
+        .env("EXIT_ZERO_ON_NO_ROWS", "true")
+        .env("JSON_ROOT_KEY", "results")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(&output_file).unwrap(),
+        "{\"results\":[]}\n"
+    );
+}
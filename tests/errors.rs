@@ -0,0 +1,39 @@
+use std::process::Command;
+
+#[test]
+fn connection_errors_never_show_the_password() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_gold_digger"))
+        .env("DATABASE_URL", "mysql://user:hunter2/x?y#z@127.0.0.1:1/db")
+        .env("DATABASE_QUERY", "SELECT 1")
+        .env("OUTPUT_FILE", dir.path().join("out.csv"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("hunter2"), "{}", stderr);
+}
+
+#[test]
+fn benchmark_cannot_page() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_gold_digger"))
+        .env("DATABASE_URL", "mysql://user@127.0.0.1:1/db")
+        .env("DATABASE_QUERY", "SELECT id FROM t")
+        .env("OUTPUT_FILE", dir.path().join("out.csv"))
+        .env("BENCHMARK", "3")
+        .env("PAGE_SIZE", "100")
+        .env("ORDER_BY", "id")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(255));
+    // The reason is only printed with the verbose feature.
+    if cfg!(feature = "verbose") {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("BENCHMARK cannot be combined with PAGE_SIZE"),
+            "{}",
+            stderr
+        );
+    }
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use mysql::prelude::Queryable;
+
+#[test]
+fn explain_refuses_statements_that_change_data() {
+    let mut conn = common::create_table("gd_explain", "id INT PRIMARY KEY, seen INT", 3, "n, 0");
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    for query in [
+        "UPDATE gd_explain SET seen = 1",
+        "WITH ids AS (SELECT id FROM gd_explain) DELETE FROM gd_explain",
+        "SELECT id FROM gd_explain; UPDATE gd_explain SET seen = 1",
+    ] {
+        let status = common::gold_digger(query, output_file.to_str().unwrap())
+            .env("EXPLAIN_ONLY", "true")
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(255), "{}", query);
+    }
+    let seen: Vec<(i32, i32)> = conn
+        .query("SELECT COUNT(*), COALESCE(SUM(seen), 0) FROM gd_explain")
+        .unwrap();
+    assert_eq!(seen, [(3, 0)]);
+
+    let plan_file = dir.path().join("plan.txt");
+    let status = common::gold_digger(
+        "SELECT id FROM gd_explain WHERE seen = 0",
+        output_file.to_str().unwrap(),
+    )
+    .env("EXPLAIN_ONLY", "true")
+    .env("EXPLAIN_OUTPUT", &plan_file)
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert!(!std::fs::read_to_string(&plan_file).unwrap().is_empty());
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::{fs, process::Command, thread, time::Duration};
+
+use gold_digger::interrupt::EXIT_INTERRUPTED;
+
+#[test]
+fn sigint_leaves_partial_output_or_none() {
+    common::create_table(
+        "gd_interrupt",
+        "id INT PRIMARY KEY, name VARCHAR(64)",
+        500_000,
+        "n, CONCAT('name-', n)",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let mut child = common::gold_digger(
+        "SELECT id, name FROM gd_interrupt ORDER BY id",
+        output_file.to_str().unwrap(),
+    )
+    .env("FORMAT_OPTION_QUOTE", "necessary")
+    .spawn()
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(500));
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let status = child.wait().unwrap();
+
+    // The export may have finished before the signal arrived.
+    if status.code() == Some(0) {
+        let output = fs::read_to_string(&output_file).unwrap();
+        assert_eq!(output.lines().count(), 500_001);
+        return;
+    }
+    assert_eq!(status.code(), Some(EXIT_INTERRUPTED));
+    if let Ok(output) = fs::read_to_string(&output_file) {
+        // Writing stops between rows, so every line is a complete record.
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("id,name"));
+        for (index, line) in (1..).zip(lines) {
+            assert_eq!(line, format!("{},name-{}", index, index));
+        }
+        assert!(output.is_empty() || output.ends_with('\n'));
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+use mysql::serde_json::{self, Value};
+use sha2::{Digest, Sha256};
+
+#[test]
+fn lists_every_chunk() {
+    common::create_table("gd_manifest", "id INT PRIMARY KEY", 5, "n");
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let manifest = dir.path().join("manifest.json");
+    let output = common::gold_digger(
+        "SELECT id FROM gd_manifest ORDER BY id",
+        output_file.to_str().unwrap(),
+    )
+    .env("CHUNK_ROWS", "2")
+    .env("ATOMIC_OUTPUT", "true")
+    .env("MANIFEST", &manifest)
+    .output()
+    .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let manifest: Value = serde_json::from_slice(&fs::read(&manifest).unwrap()).unwrap();
+    let files = manifest["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+    for (index, (file, rows)) in (1..).zip(files.iter().zip([2, 2, 1])) {
+        let path = dir.path().join(format!("out-{:05}.csv", index));
+        assert_eq!(file["path"], path.to_str().unwrap());
+        assert_eq!(file["rows"], rows);
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(file["bytes"], contents.len());
+        let sha256: String = Sha256::digest(&contents)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(file["sha256"], sha256);
+    }
+    // Only the chunks and the manifest, no temporary files.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 4);
+}
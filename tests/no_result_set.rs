@@ -0,0 +1,38 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+#[test]
+fn statements_without_a_result_set_are_allowed_on_request() {
+    common::create_table(
+        "gd_no_result_set",
+        "id INT PRIMARY KEY, seen INT",
+        3,
+        "n, 0",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let update = "UPDATE gd_no_result_set SET seen = 1";
+
+    let status = common::gold_digger(update, output_file.to_str().unwrap())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(1));
+
+    let status = common::gold_digger(update, output_file.to_str().unwrap())
+        .env("ALLOW_NO_RESULT_SET", "true")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert!(!output_file.exists());
+
+    // An empty result set is still "no records", with or without the flag.
+    let status = common::gold_digger(
+        "SELECT id FROM gd_no_result_set WHERE seen = 0",
+        output_file.to_str().unwrap(),
+    )
+    .env("ALLOW_NO_RESULT_SET", "true")
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(1));
+}
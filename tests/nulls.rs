@@ -0,0 +1,35 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn null_text_is_written_but_still_counts_as_null() {
+    common::create_table(
+        "gd_nulls",
+        "id INT PRIMARY KEY, v INT NULL",
+        3,
+        "n, IF(n = 2, NULL, n)",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let output = common::gold_digger(
+        "SELECT id, v FROM gd_nulls ORDER BY id DESC",
+        output_file.to_str().unwrap(),
+    )
+    .env("NULL_TEXT", "\\N")
+    .env("NULL_REPORT", "true")
+    .env("SORT_BY", "v")
+    .env("FORMAT_OPTION_QUOTE", "necessary")
+    .output()
+    .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stderr.contains("v: 1 nulls of 3 rows"), "{}", stderr);
+    // NULL sorts first, ahead of the numbers.
+    assert_eq!(
+        fs::read_to_string(&output_file).unwrap(),
+        "id,v\n2,\\N\n1,1\n3,3\n"
+    );
+}
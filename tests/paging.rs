@@ -0,0 +1,33 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn pages_cover_every_row_in_order() {
+    common::create_table(
+        "gd_paging",
+        "id INT PRIMARY KEY, name VARCHAR(64)",
+        2_500,
+        "n, CONCAT('name-', n)",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let status = common::gold_digger(
+        "SELECT id, name FROM gd_paging",
+        output_file.to_str().unwrap(),
+    )
+    .env("PAGE_SIZE", "1000")
+    .env("ORDER_BY", "id")
+    .env("FORMAT_OPTION_QUOTE", "necessary")
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(0));
+
+    let output = fs::read_to_string(&output_file).unwrap();
+    let mut lines = output.lines();
+    assert_eq!(lines.next(), Some("id,name"));
+    let expected: Vec<String> = (1..=2_500).map(|n| format!("{},name-{}", n, n)).collect();
+    assert_eq!(lines.collect::<Vec<&str>>(), expected);
+}
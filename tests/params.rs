@@ -0,0 +1,42 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn binds_named_parameters() {
+    common::create_table(
+        "gd_params",
+        "id INT PRIMARY KEY, name VARCHAR(64), active TINYINT",
+        4,
+        "n, CONCAT('name-', n), n % 2",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let status = common::gold_digger(
+        "SELECT id, name FROM gd_params WHERE id >= :min_id AND active = :active ORDER BY id",
+        output_file.to_str().unwrap(),
+    )
+    .env("QUERY_PARAMS_JSON", r#"{"min_id": 2, "active": true}"#)
+    .env("FORMAT_OPTION_QUOTE", "necessary")
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(&output_file).unwrap(),
+        "id,name\n3,name-3\n"
+    );
+}
+
+#[test]
+fn rejects_invalid_parameters_before_connecting() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let status = common::gold_digger("SELECT :ids", output_file.to_str().unwrap())
+        .env("QUERY_PARAMS_JSON", r#"{"ids": [1, 2]}"#)
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(255));
+    assert!(!output_file.exists());
+}
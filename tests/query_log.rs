@@ -0,0 +1,37 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn logs_failed_queries_with_their_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let log_file = dir.path().join("queries.log");
+
+    let status = common::gold_digger("SELECT 1 AS id", output_file.to_str().unwrap())
+        .env("QUERY_LOG_FILE", &log_file)
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    let status = common::gold_digger(
+        "SELECT * FROM gd_no_such_table",
+        output_file.to_str().unwrap(),
+    )
+    .env("QUERY_LOG_FILE", &log_file)
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(1));
+
+    let log = fs::read_to_string(&log_file).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("\"rows\":1"), "{}", lines[0]);
+    assert!(lines[0].contains("\"error\":null"), "{}", lines[0]);
+    assert!(
+        lines[1].contains("gd_no_such_table' doesn't exist"),
+        "{}",
+        lines[1]
+    );
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+fn export(query: &str, result_set: Option<&str>) -> (Option<i32>, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let mut command = common::gold_digger(query, output_file.to_str().unwrap());
+    command.env("FORMAT_OPTION_QUOTE", "necessary");
+    if let Some(result_set) = result_set {
+        command.env("RESULT_SET", result_set);
+    }
+    let status = command.status().unwrap();
+    (
+        status.code(),
+        fs::read_to_string(&output_file).unwrap_or_default(),
+    )
+}
+
+#[test]
+fn exports_the_requested_result_set() {
+    let query = "SELECT 1 AS first; SELECT 2 AS second; SELECT 3 AS third";
+    assert_eq!(
+        export(query, Some("2")),
+        (Some(0), "second\n2\n".to_string())
+    );
+    assert_eq!(export(query, None), (Some(0), "third\n3\n".to_string()));
+    assert_eq!(export(query, Some("4")).0, Some(1));
+}
+
+#[test]
+fn skips_trailing_empty_result_sets_by_default() {
+    let query = "SELECT 1 AS first; SELECT 2 AS second; SELECT 3 AS third FROM DUAL WHERE FALSE";
+    assert_eq!(export(query, None), (Some(0), "second\n2\n".to_string()));
+    // Asking for the empty one explicitly still finds no records.
+    assert_eq!(export(query, Some("3")).0, Some(1));
+}
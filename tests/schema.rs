@@ -0,0 +1,30 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn writes_column_definitions_without_running_the_query() {
+    common::create_table(
+        "gd_schema",
+        "id INT NOT NULL PRIMARY KEY, name VARCHAR(64), created DATETIME",
+        1,
+        "n, 'a', NOW()",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let status = common::gold_digger(
+        "SELECT id, name, created FROM gd_schema WHERE id = :id",
+        output_file.to_str().unwrap(),
+    )
+    .env("SCHEMA_ONLY", "true")
+    .env("FORMAT_OPTION_QUOTE", "necessary")
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(&output_file).unwrap(),
+        "name,type,nullable\nid,INT,NO\nname,VARCHAR,YES\ncreated,DATETIME,YES\n"
+    );
+}
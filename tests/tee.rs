@@ -0,0 +1,29 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn stdout_mirrors_the_output_file() {
+    common::create_table(
+        "gd_tee",
+        "id INT PRIMARY KEY, name VARCHAR(64)",
+        50,
+        "n, CONCAT('name-', n)",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let output = common::gold_digger(
+        "SELECT id, name FROM gd_tee ORDER BY id",
+        output_file.to_str().unwrap(),
+    )
+    .env("TEE", "true")
+    .output()
+    .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let written = fs::read(&output_file).unwrap();
+    assert!(written.starts_with(b"\"id\",\"name\""));
+    assert_eq!(output.stdout, written);
+}
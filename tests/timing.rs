@@ -0,0 +1,54 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+use mysql::serde_json::{self, Value};
+
+#[test]
+fn timings_are_written_however_the_run_succeeds() {
+    common::create_table("gd_timing", "id INT PRIMARY KEY", 3, "n");
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    let timing_output = dir.path().join("timings.json");
+
+    for (query, flag) in [
+        ("SELECT id FROM gd_timing ORDER BY id", None),
+        (
+            "SELECT id FROM gd_timing WHERE id > 10",
+            Some("EXIT_ZERO_ON_NO_ROWS"),
+        ),
+        ("DO 1", Some("ALLOW_NO_RESULT_SET")),
+        (
+            "UPDATE gd_timing SET id = id WHERE id = 1",
+            Some("ALLOW_DML"),
+        ),
+    ] {
+        fs::remove_file(&timing_output).ok();
+        let mut command = common::gold_digger(query, output_file.to_str().unwrap());
+        command.env("TIMING_OUTPUT", &timing_output);
+        if let Some(flag) = flag {
+            command.env(flag, "true");
+        }
+        let output = command.output().unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", query);
+
+        let timings: Value = serde_json::from_slice(&fs::read(&timing_output).unwrap()).unwrap();
+        for key in [
+            "connect_ms",
+            "query_ms",
+            "convert_ms",
+            "write_ms",
+            "total_ms",
+        ] {
+            assert!(
+                timings[key].is_u64(),
+                "{} has no {}: {}",
+                query,
+                key,
+                timings
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn headers_carry_the_column_types() {
+    common::create_table(
+        "gd_typed_header",
+        "id INT PRIMARY KEY, name VARCHAR(64), price DECIMAL(8, 2), created DATETIME",
+        2,
+        "n, CONCAT('name-', n), n * 1.5, '2024-01-02 03:04:05'",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let query = "SELECT id, name, price, created FROM gd_typed_header ORDER BY id";
+
+    let output_file = dir.path().join("row.csv");
+    let status = common::gold_digger(query, output_file.to_str().unwrap())
+        .env("TYPED_HEADER", "row")
+        .env("FORMAT_OPTION_QUOTE", "necessary")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    let output = fs::read_to_string(&output_file).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines[..2],
+        ["id,name,price,created", "INT,VARCHAR,DECIMAL,DATETIME"]
+    );
+    assert_eq!(lines.len(), 4);
+
+    let output_file = dir.path().join("inline.csv");
+    let status = common::gold_digger(query, output_file.to_str().unwrap())
+        .env("TYPED_HEADER", "inline")
+        .env("FORMAT_OPTION_QUOTE", "necessary")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    let output = fs::read_to_string(&output_file).unwrap();
+    assert_eq!(
+        output.lines().next(),
+        Some("id:INT,name:VARCHAR,price:DECIMAL,created:DATETIME")
+    );
+}
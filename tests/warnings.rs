@@ -0,0 +1,25 @@
+#![cfg(feature = "integration")]
+
+mod common;
+
+#[test]
+fn warnings_fail_the_export_on_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = dir.path().join("out.csv");
+    // Truncated incorrect INTEGER value: 'abc'
+    let query = "SELECT CAST('abc' AS SIGNED) AS n";
+
+    let output = common::gold_digger(query, output_file.to_str().unwrap())
+        .env("ABORT_ON_WARNING", "true")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1292"));
+    assert!(!output_file.exists());
+
+    let status = common::gold_digger(query, output_file.to_str().unwrap())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert!(output_file.exists());
+}